@@ -0,0 +1,224 @@
+use std::{fmt, future::{ready, Ready}};
+use actix_web::{
+    body::MessageBody,
+    dev::{Payload, ServiceRequest, ServiceResponse},
+    http::header::{HeaderMap, AUTHORIZATION},
+    middleware::Next,
+    web::Data,
+    Error, FromRequest, HttpMessage, HttpRequest
+};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use crate::{config::Config, libs::Errors, store::LibraryStore};
+
+/// Peran user, disimpan di dokumen user
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    /// Kelola user dan semua library
+    Admin,
+    /// Petugas perpustakaan, boleh ubah buku dan genre di library siapa aja
+    Librarian,
+    /// Cuma boleh cari dan baca library sendiri
+    #[default]
+    Member
+}
+
+impl fmt::Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Role::Admin => write!(f, "admin"),
+            Role::Librarian => write!(f, "librarian"),
+            Role::Member => write!(f, "member")
+        }
+    }
+}
+
+/// Hal yang bisa dilakukan lewat API, dicek per route
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Permission {
+    /// Lihat, buat dan hapus user
+    ManageUsers,
+    /// Lihat dan ubah akun sendiri, termasuk ganti API key
+    ManageAccount,
+    /// Cari dan baca buku dan genre
+    ReadLibrary,
+    /// Tambah, ubah dan hapus buku dan genre
+    EditLibrary,
+    /// Bertindak sebagai peminjam di library mana aja, misal antre buku.
+    /// Member cuma boleh atas nama sendiri, dicek lagi dengan require_patron
+    ActAsPatron
+}
+
+impl fmt::Display for Permission {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Permission::ManageUsers => write!(f, "manage users"),
+            Permission::ManageAccount => write!(f, "manage the account"),
+            Permission::ReadLibrary => write!(f, "read the library"),
+            Permission::EditLibrary => write!(f, "edit the library"),
+            Permission::ActAsPatron => write!(f, "act as a patron")
+        }
+    }
+}
+
+/// Siapa yang memanggil API, diisi oleh middleware authenticate
+#[derive(Clone)]
+pub struct Caller {
+    /// Kosong kalo yang manggil admin dari konfigurasi
+    pub user_id: Option<String>,
+    pub role: Role
+}
+
+impl Caller {
+    fn owns(&self, user_id: Option<&str>) -> bool {
+        user_id.is_some() && self.user_id.as_deref() == user_id
+    }
+
+    /// Matriks izin, user_id itu pemilik library atau akun yang mau dipegang
+    pub fn can(&self, permission: Permission, user_id: Option<&str>) -> bool {
+        match (self.role, permission) {
+            (Role::Admin, _) | (_, Permission::ActAsPatron) => true,
+            (Role::Librarian, Permission::ReadLibrary | Permission::EditLibrary) => true,
+            (_, Permission::ManageAccount) => self.owns(user_id),
+            (Role::Member, Permission::ReadLibrary) => self.owns(user_id),
+            _ => false
+        }
+    }
+
+    /// Member cuma boleh bertindak atas nama sendiri, misal pesan atau perpanjang, petugas boleh atas nama siapa aja
+    pub fn require_patron(&self, patron: &str) -> Result<(), Errors> {
+        match self.role {
+            Role::Member if self.user_id.as_deref() != Some(patron) => Err(Errors::Forbidden(format!("Role member is not allowed to act for patron: {}", patron))),
+            _ => Ok(())
+        }
+    }
+
+    pub fn require(&self, permission: Permission, user_id: Option<&str>) -> Result<(), Errors> {
+        if self.can(permission, user_id) {
+            return Ok(());
+        }
+        Err(Errors::Forbidden(match (permission, user_id) {
+            (Permission::ManageUsers, _) | (_, None) => format!("Role {} is not allowed to {}", self.role, permission),
+            (_, Some(id)) => format!("Role {} is not allowed to {} of user: {}", self.role, permission, id)
+        }))
+    }
+}
+
+impl FromRequest for Caller {
+    type Error = Errors;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        ready(req.extensions().get::<Caller>().cloned().ok_or(Errors::Unauthorized))
+    }
+}
+
+/// Hash SHA-256 dari rahasia API key, yang disimpan di dokumen user cuma hashnya
+pub fn hash_secret(secret: &str) -> String {
+    Sha256::digest(secret.as_bytes()).iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Bikin rahasia API key baru, hasilnya (rahasia, hash)
+pub fn generate_secret() -> (String, String) {
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    let secret: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+    let hash = hash_secret(&secret);
+    (secret, hash)
+}
+
+/// API key yang dikasih ke user bentuknya user_id.rahasia
+pub fn format_api_key(user_id: &str, secret: &str) -> String {
+    format!("{}.{}", user_id, secret)
+}
+
+/// Bandingin dua teks dengan waktu yang sama, supaya isinya ga bisa ditebak dari waktunya
+fn constant_time_eq(a: &str, b: &str) -> bool {
+    a.len() == b.len() && a.bytes().zip(b.bytes()).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// Ambil API key dari header Authorization: Bearer <key> atau X-API-Key: <key>.
+/// Authorization jenis lain, misal Basic dari proxy, ga dianggap dan X-API-Key tetap dipakai
+fn api_key_from(headers: &HeaderMap) -> Option<String> {
+    let bearer = headers.get(AUTHORIZATION).and_then(|v| v.to_str().ok()).and_then(|v| v.strip_prefix("Bearer "));
+    if let Some(key) = bearer {
+        return Some(key.trim().to_string());
+    }
+    headers.get("X-API-Key").and_then(|v| v.to_str().ok()).map(|k| k.trim().to_string())
+}
+
+/// Cari siapa pemilik API key, admin dari konfigurasi atau user di list user
+async fn resolve_caller(key: &str, cfg: &Config, db: &dyn LibraryStore) -> Result<Caller, Errors> {
+    if let Some(admin_key) = &cfg.auth.admin_api_key {
+        if constant_time_eq(key, admin_key) {
+            return Ok(Caller { user_id: None, role: Role::Admin });
+        }
+    }
+
+    // Pisahin user id dan rahasianya lalu cocokin hashnya dengan yang disimpan
+    let (user_id, secret) = key.rsplit_once('.').ok_or(Errors::Unauthorized)?;
+    let response = db.get_single_document(&cfg.library.user_index, user_id, Some("api_key_hash,role".to_string())).await?;
+    if !response.status_code().is_success() {
+        return Err(Errors::Unauthorized);
+    }
+    match response.body["api_key_hash"].as_str() {
+        Some(stored) if constant_time_eq(&hash_secret(secret), stored) => Ok(Caller {
+            user_id: Some(user_id.to_string()),
+            role: serde_json::from_value(response.body["role"].clone()).unwrap_or_default()
+        }),
+        _ => Err(Errors::Unauthorized)
+    }
+}
+
+/// Middleware yang cek API key lalu simpan Caller di request
+pub async fn authenticate(req: ServiceRequest, next: Next<impl MessageBody>) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    let cfg = req.app_data::<Data<Config>>().cloned().ok_or(Errors::Unknown)?;
+    let db = req.app_data::<Data<dyn LibraryStore>>().cloned().ok_or(Errors::Unknown)?;
+
+    let caller = if cfg.auth.enabled {
+        let key = api_key_from(req.headers()).ok_or(Errors::Unauthorized)?;
+        resolve_caller(&key, &cfg, db.get_ref()).await?
+    } else {
+        Caller { user_id: None, role: Role::Admin }
+    };
+    req.extensions_mut().insert(caller);
+    next.call(req).await
+}
+
+/// Cek izin caller untuk route ini, user_id diambil dari path kalo ada
+fn check_permission(req: &ServiceRequest, permission: Permission) -> Result<(), Errors> {
+    let caller = req.extensions().get::<Caller>().cloned().ok_or(Errors::Unauthorized)?;
+    caller.require(permission, req.match_info().get("user_id"))
+}
+
+/// Middleware untuk route kelola user, cuma admin
+pub async fn can_manage_users(req: ServiceRequest, next: Next<impl MessageBody>) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    check_permission(&req, Permission::ManageUsers)?;
+    next.call(req).await
+}
+
+/// Middleware untuk route akun sendiri, pemilik akun atau admin
+pub async fn can_manage_account(req: ServiceRequest, next: Next<impl MessageBody>) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    check_permission(&req, Permission::ManageAccount)?;
+    next.call(req).await
+}
+
+/// Middleware untuk route baca dan cari buku
+pub async fn can_read_library(req: ServiceRequest, next: Next<impl MessageBody>) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    check_permission(&req, Permission::ReadLibrary)?;
+    next.call(req).await
+}
+
+/// Middleware untuk route yang dipakai peminjam sendiri, semua user yang login boleh
+pub async fn can_act_as_patron(req: ServiceRequest, next: Next<impl MessageBody>) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    check_permission(&req, Permission::ActAsPatron)?;
+    next.call(req).await
+}
+
+/// Middleware untuk route yang ubah buku atau genre
+pub async fn can_edit_library(req: ServiceRequest, next: Next<impl MessageBody>) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    check_permission(&req, Permission::EditLibrary)?;
+    next.call(req).await
+}
@@ -1,350 +1,504 @@
-use std::io::Read;
+use std::{collections::HashMap, io::Read};
 
-use crate::{database::Database, structs::*, libs::*};
+use crate::{config::Config, copy::available_copies, cursor::{check_window, search_page, wants_cursor}, duplicate::{book_key, find_existing, known_keys}, facet::{facet_aggs, read_facets}, filter::search_filters, sort::search_sort, idempotency::{fingerprint, run_once, IdempotencyKey}, isbn::{normalize, to_isbn10}, mapping::{book_index, user_books_prefix}, store::LibraryStore, structs::*, validate::{check_book, parse_fields, RETURN_FIELDS, SEARCH_FIELDS}, libs::*};
 use actix_multipart::form::MultipartForm;
-use actix_web::{web::{self, Data}, HttpResponse, http::StatusCode};
+use actix_web::{web::{self, Data}, HttpResponse};
 use serde_json::{json, Value};
 
 /// Ambil data buku dari indeks
-pub async fn get_book(path: web::Path<UserBookID>, query: web::Query<OptionalReturnFields>, db: Data::<Database>) -> HttpResponse {  
+pub async fn get_book(path: web::Path<UserBookID>, query: web::Query<OptionalReturnFields>, cfg: Data<Config>, db: Data::<dyn LibraryStore>) -> Result<HttpResponse, Errors> {
 
-    // Jadikan Lowercase lalu diformat jadi bentuk userid.genre 
+    // Jadikan Lowercase lalu diformat jadi bentuk userid.genre
     let genre = path.genre.to_lowercase();
-    let genre_index = &format!("{}.{}", &path.user_id.to_lowercase(), &genre);
+    let genre_index = &book_index(&path.user_id, &genre);
 
     // Cek kalo userid sama genre emang ada, kalo iya lanjut
-    match check_userid_genre(&path.user_id, &genre, &db).await{
-        Ok(_) => (),
-        Err((s, e)) => return HttpResponse::build(s).json(json!({"error": e.to_string()}))
+    check_userid_genre(&path.user_id, &genre, &cfg, db.get_ref()).await?;
+
+    // Field yang diminta harus dikenal
+    let return_fields = match &query.return_fields {
+        Some(f) => Some(parse_fields("return_fields", f, &RETURN_FIELDS)?.join(",")),
+        None => None
     };
 
     // Sekarang coba ambil dokumennya, kalo gagal kirim eror, kalo berhasil kirim bukunya
-    let response = db.get_single_document(genre_index, &path.book_id, query.return_fields.clone()).await.unwrap();
-    
+    let response = db.get_single_document(genre_index, &path.book_id, return_fields).await?;
+
     if !response.status_code().is_success() {
-        let e = match response.status_code(){
-            StatusCode::NOT_FOUND => Errors::BookNotFound(path.book_id.to_owned()).to_string(),
-            _ => Errors::Unknown.to_string()
-        };
-        return HttpResponse::build(response.status_code()).json(json!({"error": e}));
+        return Err(store_error(&response, Errors::BookNotFound(path.book_id.to_owned())));
     }
 
-    HttpResponse::build(response.status_code()).json(response.json::<Value>().await.unwrap())
+    Ok(HttpResponse::build(response.status_code()).json(response.body))
+}
+
+/// Cari buku pakai isbn di semua genre milik user, ISBN-10 dan ISBN-13 dianggap sama
+pub async fn get_book_by_isbn(path: web::Path<UserIsbn>, cfg: Data<Config>, db: Data::<dyn LibraryStore>) -> Result<HttpResponse, Errors> {
+    // Cek kalo elastic hidup dan usernya ada
+    check_server(db.get_ref()).await?;
+    let genres = get_user_genre_list(&path.user_id, &cfg, db.get_ref()).await?;
+    if genres.is_empty() {
+        return Err(Errors::BookNotFound(path.isbn.to_string()));
+    }
+
+    // Isbn yang disimpan sudah ISBN-13, isbn_raw buat jaga-jaga data lama yang belum dirapikan
+    let isbn13 = normalize(&path.isbn).map_err(|e| Errors::Validation(format!("Invalid ISBN '{}': {}", path.isbn, e)))?;
+    let mut forms = vec![isbn13.clone(), path.isbn.trim().to_string()];
+    forms.extend(to_isbn10(&isbn13));
+    let body = json!({
+        "query": {
+            "bool": {
+                "should": [
+                    {"terms": {"isbn": forms}},
+                    {"terms": {"isbn_raw": forms}}
+                ],
+                "minimum_should_match": 1
+            }
+        }
+    });
+
+    let prefix = user_books_prefix(&path.user_id);
+    let response = db.search(&format!("{}*", prefix), &body, None, Some(1)).await?;
+    if !response.status_code().is_success() {
+        return Err(store_error(&response, Errors::BookNotFound(path.isbn.to_string())));
+    }
+
+    // Genre diambil dari nama indeksnya
+    let hit = &response.body["hits"]["hits"][0];
+    if hit.is_null() {
+        return Err(Errors::BookNotFound(path.isbn.to_string()));
+    }
+    let genre = hit["_index"].as_str().unwrap_or_default().strip_prefix(&prefix).unwrap_or_default();
+    Ok(HttpResponse::Ok().json(json!({
+        "_id": hit["_id"],
+        "_index": hit["_index"],
+        "genre": genre,
+        "_source": hit["_source"]
+    })))
 }
 
 /// Cari buku di indeks dengan metode post
-pub async fn search_books(path: web::Path<UserID>, genre: web::Query<OptionalGenre>, query: web::Json<BookSearchQuery>, db: Data::<Database>) -> HttpResponse {
+pub async fn search_books(path: web::Path<UserID>, genre: web::Query<OptionalGenre>, query: web::Json<BookSearchQuery>, cfg: Data<Config>, db: Data::<dyn LibraryStore>) -> Result<HttpResponse, Errors> {
+    // Genre dari url didahulukan, kalo ga ada pakai yang di body
+    let genre = genre.into_inner().genre.or(query.genre.clone());
+    find_books(&path.user_id, genre, &query, &cfg, db.get_ref()).await
+}
 
+/// Cari buku di indeks dengan metode get
+pub async fn search_books_get(path: web::Path<UserID>, query: web::Query<BookSearchQuery>, cfg: Data<Config>, db: Data::<dyn LibraryStore>) -> Result<HttpResponse, Errors> {
+    find_books(&path.user_id, query.genre.clone(), &query, &cfg, db.get_ref()).await
+}
+
+/// Isi pencarian yang dipakai metode get dan post
+async fn find_books(user_id: &str, genre: Option<String>, query: &BookSearchQuery, cfg: &Config, db: &dyn LibraryStore) -> Result<HttpResponse, Errors> {
     // Berapa lama waktu jalannya?
     let took = std::time::Instant::now();
 
     // Antara cari di semua, atau di satu genre spesifik
-    let mut to_search = genre.genre.as_ref().unwrap_or(&"*".to_string()).to_lowercase();
-    to_search = if to_search.eq(""){
-        "*".to_string()
-    } else {
-        to_search
+    let to_search = match genre.as_deref().map(str::to_lowercase) {
+        Some(g) if !g.is_empty() => g,
+        _ => "*".to_string()
     };
-    let genre_index = format!("{}.{}", &path.user_id.to_lowercase(), &to_search);
+    let genre_index = book_index(user_id, &to_search);
 
     // Cek kalo user atau genre ada, lalu kalo ketemu, genre engga ada, tetap lanjut tapi ambil dari semua genre
-    match check_userid_genre(&path.user_id, &to_search, &db).await{
+    match check_userid_genre(user_id, &to_search, cfg, db).await{
         Ok(_) => (),
-        Err((s, e)) => 
-            match e {
-                Errors::GenreNotFound(_) => 
-                    if query.genre.is_some() && !(query.genre.as_ref().unwrap().eq("*") || query.genre.as_ref().unwrap().eq("")){
-                        return HttpResponse::build(s).json(json!({"error": e.to_string()}))
-                    },
-                _ => return HttpResponse::build(s).json(json!({"error": e.to_string()}))
-            }
+        Err(Errors::GenreNotFound(_)) if to_search == "*" => (),
+        Err(e) => return Err(e)
     };
 
     // Hapus wildcard kalo ada lalu hapus spasi kalo berlebihan
-    let terms = if query.search_term.is_some() {
-        let mut z = query.search_term.as_ref().unwrap().replace("*", " ").replace(r" *", "* ");
+    let terms = if let Some(term) = &query.search_term {
+        let mut z = term.replace('*', " ").replace(r" *", "* ");
         z.push('*');
         Some(z)
     } else {
         None
     };
 
-    // Buat bodynya untuk search
-    let body = if let Some(term) = terms {
-        // Kalo ada yang mau dicari
+    // Field yang dicari dan yang dikirim balik, kalo ga diisi semua
+    let search_fields = match &query.search_fields {
+        Some(f) => Some(parse_fields("search_fields", f, &SEARCH_FIELDS)?),
+        None => None
+    };
+    let includes = match &query.return_fields {
+        Some(f) => json!(parse_fields("return_fields", f, &RETURN_FIELDS)?),
+        None => json!("*")
+    };
+
+    // Buat query untuk search
+    let search = if let Some(term) = terms {
+        // Kalo ada yang mau dicari, judul juga dicari pakai analyzer sesuai bahasa bukunya
+        let raw = query.search_term.as_deref().unwrap_or_default();
+        let mut query_string = json!({
+            "query": term,
+            "type": "cross_fields"
+        });
+        if let Some(fields) = &search_fields {
+            query_string["fields"] = json!(fields);
+        }
+        let mut should = vec![json!({"query_string": query_string})];
+        if search_fields.as_ref().map(|f| f.iter().any(|f| f == "judul")).unwrap_or(true) {
+            should.push(language_query("id", raw));
+            should.push(language_query("en", raw));
+        }
         json!({
-            "_source": {
-                "includes": "*"
-            },
-            "query": {
-                "query_string": {
-                    "query": term,
-                    "type": "cross_fields"
-                }
+            "bool": {
+                "should": should,
+                "minimum_should_match": 1
             }
         })
     } else {
         // Kalo engga ada
         json!({
-            "_source": {
-                "includes": "*"
-            },
-            "query": {
-                "match_all": {} 
-            },
+            "match_all": {}
         })
     };
 
-    // Kirim permintaan cari
-    let response = db.search(&genre_index, 
-            body, 
-            query.from, 
-            query.count
-        ).await.unwrap()
-        .json::<Value>()
-        .await.unwrap();
+    // Filter dan nilai facet yang dipilih menyaring hasilnya tanpa mengubah skor
+    let filters = search_filters(query)?;
+    let mut body = json!({
+        "_source": {
+            "includes": includes
+        },
+        "query": if filters.is_empty() {
+            search
+        } else {
+            json!({"bool": {"must": search, "filter": filters}})
+        },
+        "sort": search_sort(query)?
+    });
+    if let Some(aggs) = facet_aggs(query)? {
+        body["aggs"] = aggs;
+    }
 
-    HttpResponse::Ok().json(json!({
+    // Kirim permintaan cari, lewat cursor kalo diminta
+    let count = query.count.unwrap_or(cfg.library.default_page_size);
+    let (response, next_cursor) = if wants_cursor(query)? {
+        let (response, next) = search_page(&genre_index, &body, query, count, cfg, db).await?;
+        (response, Some(next))
+    } else {
+        check_window(query.from, count)?;
+        (db.search(&genre_index, &body, query.from, Some(count)).await?, None)
+    };
+    if !response.status_code().is_success() {
+        return Err(store_error(&response, Errors::GenreNotFound(to_search)));
+    }
+
+    // Tambahin jumlah eksemplar yang tersedia kalo diminta
+    let mut data = response.body["hits"]["hits"].clone();
+    if query.copies.unwrap_or(false) {
+        if let Some(hits) = data.as_array_mut() {
+            // Genre buku diambil dari nama indeksnya
+            let prefix = user_books_prefix(user_id);
+            let book = |h: &Value| (h["_index"].as_str().unwrap_or_default().strip_prefix(&prefix).unwrap_or_default().to_string(), h["_id"].as_str().unwrap_or_default().to_string());
+            let books: Vec<(String, String)> = hits.iter().map(book).collect();
+            let available = available_copies(user_id, &books, cfg, db).await?;
+            for hit in hits {
+                hit["copies_available"] = json!(available.get(&book(hit)).copied().unwrap_or(0));
+            }
+        }
+    }
+
+    let mut result = json!({
         "took": &took.elapsed().as_millis(),
-        "data": &response["hits"]["hits"],
-        "total": &response["hits"]["total"]["value"],
+        "data": &data,
+        "total": &response.body["hits"]["total"]["value"],
         "from": &query.from.unwrap_or(0),
-        "count": &query.count.unwrap_or(20)
-    }))
+        "count": &count
+    });
+    if query.facets.is_some() {
+        result["facets"] = read_facets(&response.body["aggregations"]);
+    }
+    if let Some(next) = next_cursor {
+        result["next_cursor"] = json!(next);
+    }
+    Ok(HttpResponse::Ok().json(result))
 }
 
-/// Cari buku di indeks dengan metode get
-pub async fn search_books_get(path: web::Path<UserID>, query: web::Query<BookSearchQuery>, db: Data::<Database>) -> HttpResponse {
-    // Berapa lama waktu jalannya?
+/// Cari judul buku berbahasa tertentu pakai analyzer bahasanya, jadi buku-buku ketemu buku
+fn language_query(language: &str, term: &str) -> Value {
+    json!({
+        "bool": {
+            "filter": [{"term": {"bahasa": language}}],
+            "must": [{"match": {format!("judul.{}", language): {"query": term}}}]
+        }
+    })
+}
+
+/// Paling banyak saran dalam sekali minta
+const MAX_SUGGESTIONS: i64 = 20;
+
+/// Saran judul dan penulis waktu mengetik, dicocokkan dari awal tiap kata
+pub async fn suggest_books(path: web::Path<UserID>, query: web::Query<SuggestQuery>, cfg: Data<Config>, db: Data::<dyn LibraryStore>) -> Result<HttpResponse, Errors> {
     let took = std::time::Instant::now();
+    let text = query.q.trim();
+    if text.is_empty() || text.chars().count() > 100 {
+        return Err(Errors::InvalidFields(vec![FieldError::new("q", "must be 1 to 100 characters")]));
+    }
+    let count = query.count.unwrap_or(5);
+    if !(1..=MAX_SUGGESTIONS).contains(&count) {
+        return Err(Errors::InvalidFields(vec![FieldError::new("count", format!("must be between 1 and {}", MAX_SUGGESTIONS))]));
+    }
 
-    // Antara cari di semua, atau di satu genre spesifik
-    let mut to_search = query.genre.as_ref().unwrap_or(&"*".to_string()).to_lowercase();
-    to_search = if to_search.eq(""){
-        "*".to_string()
-    } else {
-        to_search
+    // Semua genre atau satu genre aja, sama seperti cari
+    let to_search = match query.genre.as_deref().map(str::to_lowercase) {
+        Some(g) if !g.is_empty() => g,
+        _ => "*".to_string()
     };
-    let genre_index = format!("{}.{}", &path.user_id.to_lowercase(), &to_search);
-
-    // Cek kalo user atau genre ada, lalu kalo ketemu, genre engga ada, tetap lanjut tapi ambil dari semua genre
-    match check_userid_genre(&path.user_id, &to_search, &db).await{
+    match check_userid_genre(&path.user_id, &to_search, &cfg, db.get_ref()).await {
         Ok(_) => (),
-        Err((s, e)) => 
-            match e {
-                Errors::GenreNotFound(_) => 
-                    if query.genre.is_some() && !(query.genre.as_ref().unwrap().eq("*") || query.genre.as_ref().unwrap().eq("")){
-                        return HttpResponse::build(s).json(json!({"error": e.to_string()}))
-                    },
-                _ => return HttpResponse::build(s).json(json!({"error": e.to_string()}))
-            }
-    };
-
-    // Hapus wildcard kalo ada lalu hapus spasi kalo berlebihan
-    let terms = if query.search_term.is_some() {
-        let mut z = query.search_term.as_ref().unwrap().replace("*", " ").replace(r" *", "* ");
-        z.push('*');
-        Some(z)
-    } else {
-        None
+        Err(Errors::GenreNotFound(_)) if to_search == "*" => (),
+        Err(e) => return Err(e)
     };
 
-    // Buat bodynya untuk search
-    let body = if let Some(term) = terms {
-        // Kalo ada yang mau dicari
-        json!({
-            "_source": {
-                "includes": "*"
-            },
-            "query": {
-                "query_string": {
-                    "query": term,
-                    "type": "cross_fields"
-                }
+    // Judul lebih diutamakan dari penulis, total hasil ga dihitung supaya cepat
+    let prefix = user_books_prefix(&path.user_id);
+    let body = json!({
+        "_source": ["judul", "penulis"],
+        "track_total_hits": false,
+        "query": {
+            "multi_match": {
+                "query": text,
+                "type": "bool_prefix",
+                "operator": "and",
+                "fields": ["judul.suggest^2", "penulis.suggest"]
             }
-        })
-    } else {
-        // Kalo engga ada
-        json!({
-            "_source": {
-                "includes": "*"
-            },
-            "query": {
-                "match_all": {} 
-            },
-        })
-    };
-
-    // Kirim permintaan cari
-    let response = db.search(&genre_index, body, query.from, query.count)
-        .await.unwrap()
-        .json::<Value>()
-        .await.unwrap();
+        }
+    });
+    let response = db.search(&format!("{}{}", prefix, to_search), &body, None, Some(count)).await?;
+    if !response.status_code().is_success() {
+        return Err(store_error(&response, Errors::GenreNotFound(to_search)));
+    }
 
-    HttpResponse::Ok().json(json!({
-        "took": &took.elapsed().as_millis(),
-        "data": &response["hits"]["hits"],
-        "total": &response["hits"]["total"]["value"],
-        "from": &query.from.unwrap_or(0),
-        "count": &query.count.unwrap_or(20)
-    }))
+    let suggestions: Vec<Value> = response.body["hits"]["hits"].as_array().into_iter().flatten().map(|hit| json!({
+        "_id": hit["_id"],
+        "genre": hit["_index"].as_str().unwrap_or_default().strip_prefix(&prefix).unwrap_or_default(),
+        "judul": hit["_source"]["judul"],
+        "penulis": hit["_source"]["penulis"]
+    })).collect();
+    Ok(HttpResponse::Ok().json(json!({
+        "took": took.elapsed().as_millis(),
+        "suggestions": suggestions
+    })))
 }
 
 // Buat buku baru
-pub async fn create_books(path: web::Path<UserGenre>, data: web::Json<Vec<BookInput>>, db: Data::<Database>) -> HttpResponse {
+pub async fn create_books(path: web::Path<UserGenre>, query: web::Query<DuplicateQuery>, key: IdempotencyKey, data: web::Json<Vec<Value>>, cfg: Data<Config>, db: Data::<dyn LibraryStore>) -> Result<HttpResponse, Errors> {
 
     // Bikin lowercase lalu cek kalo user sama genre ada
     let genre = path.genre.to_lowercase();
+    check_userid_genre(&path.user_id, &genre, &cfg, db.get_ref()).await?;
 
-    match check_userid_genre(&path.user_id, &genre, &db).await{
-        Ok(_) => (),
-        Err((s, e)) => return HttpResponse::build(s).json(json!({"error": e.to_string()})),
+    // Kirim permintaan bikin, kalo ada Idempotency-Key permintaan ulang dapat respons yang sama
+    let policy = query.on_duplicate.unwrap_or_default();
+    let print = fingerprint(&[genre.as_bytes(), &serde_json::to_vec(&policy)?, &serde_json::to_vec(&data.0)?]);
+    let books = data.into_inner();
+
+    // Untuk respons cuma yang gagal dan yang duplikat yang dikirim
+    run_once(&key, &path.user_id, &print, &cfg, db.get_ref(), async {
+        Ok(serde_json::to_value(index_books(&path.user_id, &genre, books, policy, db.get_ref()).await?)?)
+    }).await
+}
+
+const INVALID_ID: &str = "Invalid id: must be a string of 1 to 512 bytes not starting with _";
+
+/// Id buku dari luar ikut batasan id elastic
+fn valid_book_id(id: &str) -> bool {
+    !id.trim().is_empty() && id.len() <= 512 && !id.starts_with('_')
+}
+
+/// Ambil id buku dari isinya kalo dikasih, id ga ikut disimpan di dokumen
+fn take_book_id(book: &mut Value) -> Result<Option<String>, String> {
+    match book.as_object_mut().and_then(|o| o.remove("id")) {
+        None | Some(Value::Null) => Ok(None),
+        Some(Value::String(id)) if valid_book_id(&id) => Ok(Some(id)),
+        Some(_) => Err(INVALID_ID.to_string())
+    }
+}
+
+/// Rapikan isbn tiap buku, cek duplikatnya di semua genre user lalu kirim sekaligus.
+/// Buku yang gagal ga dikirim dan masuk ke daftar gagal dengan nomor urut aslinya
+async fn index_books(user_id: &str, genre: &str, books: Vec<Value>, policy: DuplicatePolicy, db: &dyn LibraryStore) -> Result<BulkResult, Errors> {
+    let mut failures = vec![];
+    let mut duplicates = vec![];
+
+    // Id dan isi bukunya dicek dulu, kunci duplikatnya pakai isbn yang sudah rapi
+    let mut checked = vec![];
+    for (num, mut book) in books.into_iter().enumerate() {
+        let prepared = take_book_id(&mut book)
+            .map_err(|e| vec![FieldError::new("id", e)])
+            .and_then(|id| check_book(&mut book, false).map(|_| id));
+        match prepared {
+            Ok(id) => checked.push((num, id, book)),
+            Err(fields) => failures.push(Failures {
+                doc_num: num,
+                reason: Errors::InvalidFields(fields.clone()).to_string(),
+                code: 400,
+                fields
+            })
+        }
     }
+    let candidates: Vec<Value> = checked.iter().map(|(_, _, b)| b.clone()).collect();
+    let existing = find_existing(user_id, &candidates, db).await?;
+
+    let mut valid = vec![];
+    let mut positions = vec![];
+    let mut seen: HashMap<String, usize> = HashMap::new();
+    for (num, id, book) in checked {
+        let key = book_key(&book);
+
+        // Buku yang sama dua kali di satu kiriman, yang kedua ga dipakai
+        if let Some(first) = key.as_ref().and_then(|k| seen.get(k)) {
+            failures.push(Failures { doc_num: num, reason: format!("Duplicate of document {} in this request", first), code: 409, ..Default::default() });
+            continue;
+        }
+        for k in known_keys(&book) {
+            seen.entry(k).or_insert(num);
+        }
 
-    // Kirim permintaan bikin
-    let response = db.index_documents(
-        &format!("{}.{}", &path.user_id.to_lowercase(), &genre), 
-        data.as_ref())
-        .await.unwrap()
-        .json::<Value>()
-        .await.unwrap();
-
-    // Untuk respons cuma yang gagal yang dikirim
-    let mut fail: Vec<Failures> = vec![];
-    if !response["errors"].is_null() {
-        if response["errors"].as_bool().unwrap(){
-            for (num, dat) in response["items"].as_array().unwrap().iter().enumerate(){
-                if !dat["index"]["error"].is_null(){
-                    fail.push(
-                        Failures {
-                            doc_num: num,
-                            reason: dat["index"]["error"]["reason"].as_str().unwrap().to_string(),
-                            code: dat["index"]["status"].as_i64().unwrap()
-                        }
-                    );
+        let (found_genre, book_id) = match key.as_ref().and_then(|k| existing.get(k)) {
+            Some(x) => x.clone(),
+            None => {
+                positions.push(num);
+                valid.push((id, book));
+                continue;
+            }
+        };
+        duplicates.push(Duplicate { doc_num: num, book_id: book_id.clone(), genre: found_genre.clone(), action: policy });
+        match policy {
+            DuplicatePolicy::Reject => failures.push(Failures {
+                doc_num: num,
+                reason: format!("Book already exists with ID: {} in genre: {}", book_id, found_genre),
+                code: 409,
+                ..Default::default()
+            }),
+            DuplicatePolicy::Skip => (),
+            // Buku yang sudah ada diupdate di genrenya sendiri
+            DuplicatePolicy::Upsert => {
+                let response = db.update_single_document(&book_index(user_id, &found_genre), &book_id, &book).await?;
+                if !response.status_code().is_success() {
+                    failures.push(Failures {
+                        doc_num: num,
+                        reason: response.body["error"]["reason"].as_str().unwrap_or("Failed to update book").to_string(),
+                        code: response.status_code().as_u16() as i64,
+                        ..Default::default()
+                    });
                 }
             }
         }
-    } else {
-        return HttpResponse::Ok().json(json!({"error": Errors::Unknown.to_string()}));
     }
-    HttpResponse::Ok().json(fail)
+
+    // Kalo semuanya gagal atau duplikat ga usah kirim ke elastic
+    if !valid.is_empty() {
+        let response = db.index_documents(&book_index(user_id, genre), &valid).await?;
+        failures.extend(bulk_failures(&response)?.into_iter().map(|f| Failures { doc_num: positions[f.doc_num], ..f }));
+    }
+    failures.sort_by_key(|f| f.doc_num);
+    Ok(BulkResult { failures, duplicates })
 }
 
 /// Update buku
-pub async fn update_book(path: web::Path<UserBookID>, data: web::Json<BookInput>, db: Data::<Database>) -> HttpResponse {
+pub async fn update_book(path: web::Path<UserBookID>, query: web::Query<UpdateQuery>, data: web::Json<Value>, cfg: Data<Config>, db: Data::<dyn LibraryStore>) -> Result<HttpResponse, Errors> {
 
     // Cek kalo user sama genre ada
     let genre = path.genre.to_lowercase();
-    match check_userid_genre(&path.user_id, &genre, &db).await{
-        Ok(_) => (),
-        Err((s, e)) => return HttpResponse::build(s).json(json!({"error": e.to_string()}))
-    };
+    check_userid_genre(&path.user_id, &genre, &cfg, db.get_ref()).await?;
+
+    // Kalo upsert, bukunya dibuat atau diganti semua
+    if query.upsert.unwrap_or(false) {
+        return upsert_book(&path, &genre, data.into_inner(), db.get_ref()).await;
+    }
+
+    // Cek isinya dan rapikan isbnnya dulu, idnya ga bisa diganti
+    let mut book = data.into_inner();
+    check_input(&mut book, &path.book_id, true)?;
 
     // Kirim permintaan update
-    match db.update_single_document(&format!("{}.{}", &path.user_id.to_lowercase(), &genre), &path.book_id, &data).await.unwrap().status_code(){
-        // Kalo ga ketemu
-        StatusCode::NOT_FOUND => HttpResponse::NotFound().json(json!({"error": Errors::BookNotFound(path.book_id.to_string()).to_string()})),
-        
-        // Kalo permintaan gagal karena request konflik atau apa
-        StatusCode::BAD_REQUEST => HttpResponse::BadRequest().json(json!({"error": Errors::BadRequest.to_string()})),
-        
-        // lain-lain
-        x => {
-            if x.is_success() {
-                // kalo sukses
-                HttpResponse::build(x).finish()
-            } else {
-                // kalo bukan sukses (engga tau eror apa)
-                HttpResponse::build(x).json(json!({"error": Errors::Unknown.to_string()}))
-            }
-        }
+    let response = db.update_single_document(&book_index(&path.user_id, &genre), &path.book_id, &book).await?;
+    if !response.status_code().is_success() {
+        return Err(store_error(&response, Errors::BookNotFound(path.book_id.to_string())));
+    }
+    Ok(HttpResponse::build(response.status_code()).finish())
+}
+
+/// Cek satu buku seperti di bulk, semua field yang salah dikirim sekaligus.
+/// Id di isi permintaan kalo ada harus sama dengan yang di url
+fn check_input(book: &mut Value, book_id: &str, partial: bool) -> Result<(), Errors> {
+    let mut errors = match take_book_id(book) {
+        Ok(Some(id)) if id != book_id => vec![FieldError::new("id", format!("does not match the url ({})", book_id))],
+        Ok(_) => vec![],
+        Err(e) => vec![FieldError::new("id", e)]
+    };
+    if let Err(fields) = check_book(book, partial) {
+        errors.extend(fields);
     }
+    if !errors.is_empty() {
+        return Err(Errors::InvalidFields(errors));
+    }
+    Ok(())
+}
+
+/// Buat atau ganti buku di id tertentu, dikirim berkali-kali hasilnya tetap sama
+async fn upsert_book(path: &UserBookID, genre: &str, mut book: Value, db: &dyn LibraryStore) -> Result<HttpResponse, Errors> {
+    // Idnya ikut aturan yang sama dengan id yang dikirim waktu bikin buku
+    if !valid_book_id(&path.book_id) {
+        return Err(Errors::Validation(INVALID_ID.to_string()));
+    }
+    check_input(&mut book, &path.book_id, false)?;
 
+    // Isinya diganti semua, 201 kalo baru dibuat dan 200 kalo diganti
+    let response = db.put_document(&book_index(&path.user_id, genre), &path.book_id, &book).await?;
+    if !response.status_code().is_success() {
+        return Err(store_error(&response, Errors::BadRequest));
+    }
+    Ok(HttpResponse::build(response.status_code()).json(json!({
+        "_id": path.book_id,
+        "result": response.body["result"]
+    })))
 }
 
 /// Hapus buku
-pub async fn delete_book(path: web::Path<UserBookID>, db: Data::<Database>) -> HttpResponse { 
+pub async fn delete_book(path: web::Path<UserBookID>, cfg: Data<Config>, db: Data::<dyn LibraryStore>) -> Result<HttpResponse, Errors> {
     // Cek kalo user sama genre ada
     let genre = path.genre.to_lowercase();
-
-    match check_userid_genre(&path.user_id, &genre, &db).await{
-        Ok(_) => (),
-        Err((s, e)) => return HttpResponse::build(s).json(json!({"error": e.to_string()}))
-    };
+    check_userid_genre(&path.user_id, &genre, &cfg, db.get_ref()).await?;
 
     // Hapus satu buku
-    match db.delete_single_document(&format!("{}.{}", &path.user_id.to_lowercase(), &genre), &path.book_id).await.unwrap().status_code() {
-        StatusCode::NOT_FOUND => HttpResponse::NotFound().json(json!({"error": Errors::BookNotFound(path.book_id.to_string()).to_string()})),
-        x => 
-            if x.is_success() {
-                HttpResponse::build(x).finish()
-            } else {
-                HttpResponse::build(x).json(json!({"error": Errors::Unknown.to_string()}))
-            }
+    let response = db.delete_single_document(&book_index(&path.user_id, &genre), &path.book_id).await?;
+    if !response.status_code().is_success() {
+        return Err(store_error(&response, Errors::BookNotFound(path.book_id.to_string())));
     }
+    Ok(HttpResponse::build(response.status_code()).finish())
 }
 
 /// Untuk upload file json supaya
-pub async fn upload_json(path: web::Path<UserGenre>, f: MultipartForm<GetFile>, db: web::Data<Database>) -> HttpResponse {
+pub async fn upload_json(path: web::Path<UserGenre>, query: web::Query<DuplicateQuery>, key: IdempotencyKey, f: MultipartForm<GetFile>, cfg: Data<Config>, db: Data::<dyn LibraryStore>) -> Result<HttpResponse, Errors> {
     // Cek kalo user sama genre ada
     let genre = &path.genre.to_lowercase();
-    match check_userid_genre(&path.user_id, genre, &db).await{
-        Ok(_) => (),
-        Err((s, e)) => return HttpResponse::build(s).json(json!({"error": e.to_string()}))
-    };
-
-    // Namanya di split supaya dari nama.json jadi [nama, json]
-    let name: Vec<&str> = f.file.file_name.as_ref().unwrap().split('.').collect();
-    let mut file = f.file.file.reopen().unwrap();
+    check_userid_genre(&path.user_id, genre, &cfg, db.get_ref()).await?;
 
-    // Cek kalo namanya json
-    if !name.last().unwrap().to_ascii_lowercase().eq("json"){
-        return HttpResponse::BadRequest().json(json!({"error": "Only JSON is Accepted"}))
+    // Cek kalo namanya json, dari nama.json ambil bagian terakhirnya
+    let extension = f.file.file_name.as_deref().and_then(|n| n.rsplit('.').next()).unwrap_or_default();
+    if !extension.eq_ignore_ascii_case("json"){
+        return Err(Errors::Validation("Only JSON is Accepted".to_string()));
     };
 
-    // Buffer kontennya
-    let mut contents = String::new();
-    
     // Baca semua data di jsonnya
-    file.read_to_string(&mut contents).unwrap();
-    
+    let mut contents = String::new();
+    f.file.file.reopen()
+        .and_then(|mut file| file.read_to_string(&mut contents))
+        .map_err(|_| Errors::Unknown)?;
+
     // Dari variabel contents jadiin bentuk serde value
-    let data: Result<Vec<Value>, _> = serde_json::from_str(&contents);
-    match data{
-        // Kalau berhasil
-        Ok(dat) => {
-            // Kirim ke elastic
-            let response = db.index_documents(
-                &format!("{}.{}", &path.user_id.to_lowercase(), &path.genre.to_lowercase()), 
-                dat.as_ref())
-                .await.unwrap()
-                .json::<Value>()
-                .await.unwrap();
-            
-            // Untuk respons cuma yang gagal yang dikirim
-            let mut fail: Vec<Failures> = vec![];
-            if !response["errors"].is_null() {
-                if response["errors"].as_bool().unwrap(){
-                    for (num, dat) in response["items"].as_array().unwrap().iter().enumerate(){
-                        if !dat["index"]["error"].is_null(){
-                            fail.push(
-                                Failures {
-                                    doc_num: num,
-                                    reason: dat["index"]["error"]["reason"].as_str().unwrap().to_string(),
-                                    code: dat["index"]["status"].as_i64().unwrap()
-                                }
-                            );
-                        }
-                    }
-                }
-            } else {
-                // Kalo erornya gatau
-                return HttpResponse::Ok().json(json!({"error": Errors::Unknown.to_string()}));
-            }
-            HttpResponse::Ok().json(fail)
-        },
-        // Kalo jsonnya invalid
-    Err(_) => HttpResponse::BadRequest().json(json!({"error": "Invalid JSON"}))
-    }
-}
\ No newline at end of file
+    let data: Vec<Value> = serde_json::from_str(&contents).map_err(|_| Errors::Validation("Invalid JSON".to_string()))?;
+
+    // Kirim ke elastic
+    let policy = query.on_duplicate.unwrap_or_default();
+    let print = fingerprint(&[genre.as_bytes(), &serde_json::to_vec(&policy)?, contents.as_bytes()]);
+
+    // Untuk respons cuma yang gagal dan yang duplikat yang dikirim
+    run_once(&key, &path.user_id, &print, &cfg, db.get_ref(), async {
+        Ok(serde_json::to_value(index_books(&path.user_id, genre, data, policy, db.get_ref()).await?)?)
+    }).await
+}
@@ -0,0 +1,552 @@
+use std::{collections::HashMap, env, fs, path::Path};
+use elasticsearch::http::Url;
+use serde::Deserialize;
+use thiserror::Error;
+
+/// Lokasi file konfigurasi kalo LIBRARY_CONFIG tidak diisi
+const DEFAULT_CONFIG_FILE: &str = "library.toml";
+
+#[derive(Error, Debug)]
+pub enum ConfigError {
+    #[error("Cannot read config file {0}: {1}")]
+    Read(String, std::io::Error),
+    #[error("Cannot parse config file {0}: {1}")]
+    Parse(String, toml::de::Error),
+    #[error("Invalid value for {0}: {1}")]
+    Env(String, String),
+    #[error("Invalid config: {0}")]
+    Invalid(String)
+}
+
+/// Konfigurasi aplikasi, dibaca dari file toml lalu ditimpa dengan environment LIBRARY_*
+#[derive(Deserialize, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub server: ServerConfig,
+    pub database: DatabaseConfig,
+    pub library: LibraryConfig,
+    pub auth: AuthConfig,
+    pub fines: FineConfig
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    pub bind_address: String,
+    pub port: u16,
+    pub workers: Option<usize>,
+    pub log_level: String,
+    pub cors_origins: Vec<String>
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            bind_address: "127.0.0.1".to_string(),
+            port: 1234,
+            workers: None,
+            log_level: "info".to_string(),
+            cors_origins: vec!["*".to_string()]
+        }
+    }
+}
+
+/// Penyimpanan yang dipakai, elastic atau di memori
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Backend {
+    #[default]
+    Elasticsearch,
+    Memory
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct DatabaseConfig {
+    pub backend: Backend,
+    pub urls: Vec<String>,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub api_key: Option<String>,
+    /// File PEM sertifikat CA untuk cluster dengan TLS
+    pub ca_cert: Option<String>,
+    pub verify_certificate: bool
+}
+
+impl Default for DatabaseConfig {
+    fn default() -> Self {
+        Self {
+            backend: Backend::Elasticsearch,
+            urls: vec!["http://127.0.0.1:9200".to_string()],
+            username: None,
+            password: None,
+            api_key: None,
+            ca_cert: None,
+            verify_certificate: true
+        }
+    }
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct LibraryConfig {
+    /// Nama indeks untuk setor list usernya
+    pub user_index: String,
+    pub default_page_size: i64,
+    /// Nama indeks untuk setor data peminjaman
+    pub loan_index: String,
+    /// Lama pinjam bawaan dalam hari
+    pub loan_days: i64,
+    /// Berapa kali satu peminjaman boleh diperpanjang
+    pub max_renewals: i64,
+    /// Tambahan hari tiap perpanjangan
+    pub renewal_days: i64,
+    /// Nama indeks untuk setor eksemplar fisik buku
+    pub copy_index: String,
+    /// Nama indeks untuk setor antrean pesanan buku
+    pub hold_index: String,
+    /// Berapa hari buku yang sudah siap ditahan untuk pemesannya
+    pub hold_pickup_days: i64,
+    /// Nama indeks untuk setor catatan denda dan pembayaran
+    pub fine_index: String,
+    /// Nama indeks untuk setor respons permintaan yang pakai Idempotency-Key
+    pub idempotency_index: String,
+    /// Berapa jam Idempotency-Key diingat sebelum boleh dipakai lagi
+    pub idempotency_hours: i64,
+    /// Berapa menit cursor pencarian tetap bisa dipakai sejak halaman terakhir diambil
+    pub cursor_minutes: i64,
+    /// Nama index template untuk semua indeks genre
+    pub book_template: String,
+    /// Jumlah shard dan replika tiap indeks genre baru
+    pub book_shards: u32,
+    pub book_replicas: u32
+}
+
+impl Default for LibraryConfig {
+    fn default() -> Self {
+        Self {
+            user_index: "users_apps".to_string(),
+            default_page_size: 20,
+            loan_index: "loans_apps".to_string(),
+            loan_days: 14,
+            max_renewals: 2,
+            renewal_days: 14,
+            copy_index: "copies_apps".to_string(),
+            hold_index: "holds_apps".to_string(),
+            hold_pickup_days: 3,
+            fine_index: "fines_apps".to_string(),
+            idempotency_index: "idempotency_apps".to_string(),
+            idempotency_hours: 24,
+            cursor_minutes: 5,
+            book_template: "library_books".to_string(),
+            book_shards: 1,
+            book_replicas: 1
+        }
+    }
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct AuthConfig {
+    /// Kalo dimatikan semua permintaan dianggap dari admin, cuma untuk laptop dan tes
+    pub enabled: bool,
+    /// API key admin awal, dipakai untuk bikin user pertama
+    pub admin_api_key: Option<String>
+}
+
+impl Default for AuthConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            admin_api_key: None
+        }
+    }
+}
+
+/// Aturan denda telat, nilai uang dalam satuan terkecil (misal rupiah)
+#[derive(Deserialize, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct FineConfig {
+    /// Denda per hari telat
+    pub daily_rate: i64,
+    /// Telat sampai sekian hari belum didenda, lewat dari itu yang dihitung cuma kelebihannya
+    pub grace_days: i64,
+    /// Denda paling besar untuk satu peminjaman
+    pub max_per_item: Option<i64>,
+    /// Peminjam dengan sisa denda di atas ini tidak bisa pinjam lagi
+    pub block_threshold: Option<i64>,
+    /// Aturan khusus per genre, yang kosong ikut aturan umum
+    pub genres: HashMap<String, FineRule>
+}
+
+impl Default for FineConfig {
+    fn default() -> Self {
+        Self {
+            daily_rate: 1000,
+            grace_days: 0,
+            max_per_item: None,
+            block_threshold: None,
+            genres: HashMap::new()
+        }
+    }
+}
+
+#[derive(Deserialize, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct FineRule {
+    pub daily_rate: Option<i64>,
+    pub grace_days: Option<i64>,
+    pub max_per_item: Option<i64>
+}
+
+impl FineConfig {
+    /// Aturan yang berlaku untuk satu genre, hasilnya (denda per hari, hari bebas denda, batas per buku)
+    pub fn rule_for(&self, genre: &str) -> (i64, i64, Option<i64>) {
+        let rule = self.genres.get(genre).cloned().unwrap_or_default();
+        (
+            rule.daily_rate.unwrap_or(self.daily_rate),
+            rule.grace_days.unwrap_or(self.grace_days),
+            rule.max_per_item.or(self.max_per_item)
+        )
+    }
+}
+
+impl Config {
+    /// Baca konfigurasi dari file (LIBRARY_CONFIG atau library.toml kalo ada), timpa dengan environment, lalu validasi
+    pub fn load() -> Result<Self, ConfigError> {
+        let mut config = match env::var("LIBRARY_CONFIG") {
+            Ok(path) => Self::from_file(&path)?,
+            Err(_) if Path::new(DEFAULT_CONFIG_FILE).exists() => Self::from_file(DEFAULT_CONFIG_FILE)?,
+            Err(_) => Self::default()
+        };
+        config.apply_env()?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn from_file(path: &str) -> Result<Self, ConfigError> {
+        let contents = fs::read_to_string(path).map_err(|e| ConfigError::Read(path.to_string(), e))?;
+        toml::from_str(&contents).map_err(|e| ConfigError::Parse(path.to_string(), e))
+    }
+
+    /// Timpa nilai dari environment LIBRARY_*
+    fn apply_env(&mut self) -> Result<(), ConfigError> {
+        set_from_env("LIBRARY_BIND_ADDRESS", &mut self.server.bind_address)?;
+        set_from_env("LIBRARY_PORT", &mut self.server.port)?;
+        set_optional_from_env("LIBRARY_WORKERS", &mut self.server.workers)?;
+        set_from_env("LIBRARY_LOG_LEVEL", &mut self.server.log_level)?;
+        set_list_from_env("LIBRARY_CORS_ORIGINS", &mut self.server.cors_origins);
+
+        if let Ok(value) = env::var("LIBRARY_DATABASE_BACKEND") {
+            self.database.backend = match value.to_lowercase().as_str() {
+                "elasticsearch" => Backend::Elasticsearch,
+                "memory" => Backend::Memory,
+                _ => return Err(ConfigError::Env("LIBRARY_DATABASE_BACKEND".to_string(), format!("unknown backend {}", value)))
+            };
+        }
+        set_list_from_env("LIBRARY_DATABASE_URLS", &mut self.database.urls);
+        set_optional_from_env("LIBRARY_DATABASE_USERNAME", &mut self.database.username)?;
+        set_optional_from_env("LIBRARY_DATABASE_PASSWORD", &mut self.database.password)?;
+        set_optional_from_env("LIBRARY_DATABASE_API_KEY", &mut self.database.api_key)?;
+        set_optional_from_env("LIBRARY_DATABASE_CA_CERT", &mut self.database.ca_cert)?;
+        set_from_env("LIBRARY_DATABASE_VERIFY_CERTIFICATE", &mut self.database.verify_certificate)?;
+
+        set_from_env("LIBRARY_USER_INDEX", &mut self.library.user_index)?;
+        set_from_env("LIBRARY_DEFAULT_PAGE_SIZE", &mut self.library.default_page_size)?;
+        set_from_env("LIBRARY_LOAN_INDEX", &mut self.library.loan_index)?;
+        set_from_env("LIBRARY_LOAN_DAYS", &mut self.library.loan_days)?;
+        set_from_env("LIBRARY_MAX_RENEWALS", &mut self.library.max_renewals)?;
+        set_from_env("LIBRARY_RENEWAL_DAYS", &mut self.library.renewal_days)?;
+        set_from_env("LIBRARY_COPY_INDEX", &mut self.library.copy_index)?;
+        set_from_env("LIBRARY_HOLD_INDEX", &mut self.library.hold_index)?;
+        set_from_env("LIBRARY_HOLD_PICKUP_DAYS", &mut self.library.hold_pickup_days)?;
+        set_from_env("LIBRARY_FINE_INDEX", &mut self.library.fine_index)?;
+        set_from_env("LIBRARY_IDEMPOTENCY_INDEX", &mut self.library.idempotency_index)?;
+        set_from_env("LIBRARY_IDEMPOTENCY_HOURS", &mut self.library.idempotency_hours)?;
+        set_from_env("LIBRARY_CURSOR_MINUTES", &mut self.library.cursor_minutes)?;
+        set_from_env("LIBRARY_BOOK_TEMPLATE", &mut self.library.book_template)?;
+        set_from_env("LIBRARY_BOOK_SHARDS", &mut self.library.book_shards)?;
+        set_from_env("LIBRARY_BOOK_REPLICAS", &mut self.library.book_replicas)?;
+
+        set_from_env("LIBRARY_AUTH_ENABLED", &mut self.auth.enabled)?;
+        set_optional_from_env("LIBRARY_AUTH_ADMIN_API_KEY", &mut self.auth.admin_api_key)?;
+
+        set_from_env("LIBRARY_FINE_DAILY_RATE", &mut self.fines.daily_rate)?;
+        set_from_env("LIBRARY_FINE_GRACE_DAYS", &mut self.fines.grace_days)?;
+        set_optional_from_env("LIBRARY_FINE_MAX_PER_ITEM", &mut self.fines.max_per_item)?;
+        set_optional_from_env("LIBRARY_FINE_BLOCK_THRESHOLD", &mut self.fines.block_threshold)?;
+        Ok(())
+    }
+
+    /// Cek semua nilai masuk akal, supaya gagalnya pas startup bukan pas dipakai
+    fn validate(&self) -> Result<(), ConfigError> {
+        let invalid = |m: &str| Err(ConfigError::Invalid(m.to_string()));
+
+        if self.server.bind_address.trim().is_empty() {
+            return invalid("server.bind_address cannot be empty");
+        }
+        if self.server.port == 0 {
+            return invalid("server.port must be between 1 and 65535");
+        }
+        if self.server.workers == Some(0) {
+            return invalid("server.workers must be at least 1");
+        }
+        if !is_log_level(&self.server.log_level) {
+            return invalid("server.log_level must be off, error, warn, info, debug, trace or module=level directives");
+        }
+        if self.server.cors_origins.is_empty() {
+            return invalid("server.cors_origins cannot be empty, use [\"*\"] to allow every origin");
+        }
+        for origin in self.server.cors_origins.iter().filter(|o| o.as_str() != "*") {
+            if !origin.starts_with("http://") && !origin.starts_with("https://") {
+                return Err(ConfigError::Invalid(format!("server.cors_origins has an invalid origin: {}", origin)));
+            }
+        }
+
+        if self.database.backend == Backend::Elasticsearch {
+            if self.database.urls.is_empty() {
+                return invalid("database.urls needs at least one url");
+            }
+            for url in &self.database.urls {
+                match Url::parse(url) {
+                    Ok(u) if u.scheme() == "http" || u.scheme() == "https" => (),
+                    _ => return Err(ConfigError::Invalid(format!("database.urls has an invalid url: {}", url)))
+                }
+            }
+        }
+        if self.database.username.is_some() != self.database.password.is_some() {
+            return invalid("database.username and database.password must be set together");
+        }
+        if self.database.username.is_some() && self.database.api_key.is_some() {
+            return invalid("use either database.username/password or database.api_key, not both");
+        }
+        if self.database.ca_cert.is_some() && !self.database.verify_certificate {
+            return invalid("database.ca_cert cannot be used when database.verify_certificate is false");
+        }
+
+        let l = &self.library;
+        let indices = [("user_index", &l.user_index), ("loan_index", &l.loan_index), ("copy_index", &l.copy_index), ("hold_index", &l.hold_index), ("fine_index", &l.fine_index), ("idempotency_index", &l.idempotency_index)];
+        for (i, (name, index)) in indices.iter().enumerate() {
+            if !is_index_name(index) {
+                return Err(ConfigError::Invalid(format!("library.{} must be a lowercase index name without dots", name)));
+            }
+            if let Some((other, _)) = indices[..i].iter().find(|(_, o)| o == index) {
+                return Err(ConfigError::Invalid(format!("library.{} must be different from library.{}", name, other)));
+            }
+        }
+        if !(1..=10_000).contains(&l.default_page_size) {
+            return invalid("library.default_page_size must be between 1 and 10000");
+        }
+        if !(1..=365).contains(&l.loan_days) {
+            return invalid("library.loan_days must be between 1 and 365");
+        }
+        if !(0..=100).contains(&l.max_renewals) {
+            return invalid("library.max_renewals must be between 0 and 100");
+        }
+        if !(1..=365).contains(&l.renewal_days) {
+            return invalid("library.renewal_days must be between 1 and 365");
+        }
+        if !(1..=30).contains(&l.hold_pickup_days) {
+            return invalid("library.hold_pickup_days must be between 1 and 30");
+        }
+        if !(1..=720).contains(&l.idempotency_hours) {
+            return invalid("library.idempotency_hours must be between 1 and 720");
+        }
+        if !(1..=60).contains(&l.cursor_minutes) {
+            return invalid("library.cursor_minutes must be between 1 and 60");
+        }
+        if !is_index_name(&l.book_template) {
+            return invalid("library.book_template must be a lowercase name without dots");
+        }
+        if !(1..=1024).contains(&l.book_shards) {
+            return invalid("library.book_shards must be between 1 and 1024");
+        }
+        if l.book_replicas > 10 {
+            return invalid("library.book_replicas must be at most 10");
+        }
+
+        if self.auth.enabled {
+            match self.auth.admin_api_key.as_deref().map(str::trim) {
+                None | Some("") => return invalid("auth.admin_api_key is required when auth.enabled is true"),
+                Some(key) if key.len() < 16 => return invalid("auth.admin_api_key must be at least 16 characters"),
+                Some(_) => ()
+            }
+        }
+
+        let f = &self.fines;
+        if f.daily_rate < 0 || f.grace_days < 0 || f.max_per_item.unwrap_or(0) < 0 || f.block_threshold.unwrap_or(0) < 0 {
+            return invalid("fines.daily_rate, grace_days, max_per_item and block_threshold cannot be negative");
+        }
+        for (genre, rule) in &f.genres {
+            if genre.to_lowercase() != *genre {
+                return Err(ConfigError::Invalid(format!("fines.genres.{} must be lowercase", genre)));
+            }
+            if rule.daily_rate.unwrap_or(0) < 0 || rule.grace_days.unwrap_or(0) < 0 || rule.max_per_item.unwrap_or(0) < 0 {
+                return Err(ConfigError::Invalid(format!("fines.genres.{} cannot have negative values", genre)));
+            }
+        }
+        Ok(())
+    }
+}
+
+fn set_from_env<T: std::str::FromStr>(name: &str, target: &mut T) -> Result<(), ConfigError>
+where T::Err: std::fmt::Display {
+    if let Ok(value) = env::var(name) {
+        *target = value.trim().parse().map_err(|e: T::Err| ConfigError::Env(name.to_string(), e.to_string()))?;
+    }
+    Ok(())
+}
+
+fn set_optional_from_env<T: std::str::FromStr>(name: &str, target: &mut Option<T>) -> Result<(), ConfigError>
+where T::Err: std::fmt::Display {
+    if let Ok(value) = env::var(name) {
+        *target = Some(value.trim().parse().map_err(|e: T::Err| ConfigError::Env(name.to_string(), e.to_string()))?);
+    }
+    Ok(())
+}
+
+/// Nilai list dari environment dipisah dengan koma
+fn set_list_from_env(name: &str, target: &mut Vec<String>) {
+    if let Ok(value) = env::var(name) {
+        *target = value.split(',').map(str::trim).filter(|v| !v.is_empty()).map(str::to_string).collect();
+    }
+}
+
+/// Nama indeks huruf kecil tanpa titik, supaya ga bentrok dengan indeks genre user.genre
+fn is_index_name(index: &str) -> bool {
+    !index.is_empty() && !index.starts_with(['-', '_', '+']) && index.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_' || c == '-')
+}
+
+/// Cek format log level env_logger, misal "info" atau "actix_web=info,library_app=debug"
+fn is_log_level(filter: &str) -> bool {
+    const LEVELS: [&str; 6] = ["off", "error", "warn", "info", "debug", "trace"];
+    !filter.trim().is_empty() && filter.split(',').all(|directive| {
+        match directive.trim().split_once('=') {
+            Some((module, level)) => !module.is_empty() && LEVELS.contains(&level.to_lowercase().as_str()),
+            None => LEVELS.contains(&directive.trim().to_lowercase().as_str())
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn valid() -> Config {
+        let mut config = Config::default();
+        config.auth.admin_api_key = Some("kunci-admin-buat-tes".to_string());
+        config
+    }
+
+    fn invalid_message(config: &Config) -> String {
+        match config.validate() {
+            Err(ConfigError::Invalid(m)) => m,
+            other => panic!("expected invalid config, got {:?}", other.map(|_| ()))
+        }
+    }
+
+    #[test]
+    fn validate() {
+        assert!(valid().validate().is_ok());
+
+        // Tanpa auth ga perlu kunci admin
+        let mut config = valid();
+        config.auth = AuthConfig { enabled: false, admin_api_key: None };
+        assert!(config.validate().is_ok());
+
+        let mut config = valid();
+        config.auth.admin_api_key = Some("pendek".to_string());
+        assert_eq!(invalid_message(&config), "auth.admin_api_key must be at least 16 characters");
+        config.auth.admin_api_key = Some("   ".to_string());
+        assert_eq!(invalid_message(&config), "auth.admin_api_key is required when auth.enabled is true");
+
+        let mut config = valid();
+        config.server.port = 0;
+        assert_eq!(invalid_message(&config), "server.port must be between 1 and 65535");
+
+        let mut config = valid();
+        config.server.cors_origins = vec!["example.com".to_string()];
+        assert_eq!(invalid_message(&config), "server.cors_origins has an invalid origin: example.com");
+
+        // Url elastic cuma dicek kalo pakai elastic
+        let mut config = valid();
+        config.database.urls = vec!["ftp://elastic".to_string()];
+        assert_eq!(invalid_message(&config), "database.urls has an invalid url: ftp://elastic");
+        config.database.backend = Backend::Memory;
+        assert!(config.validate().is_ok());
+
+        let mut config = valid();
+        config.database.username = Some("elastic".to_string());
+        assert_eq!(invalid_message(&config), "database.username and database.password must be set together");
+        config.database.password = Some("rahasia".to_string());
+        config.database.api_key = Some("kunci".to_string());
+        assert_eq!(invalid_message(&config), "use either database.username/password or database.api_key, not both");
+
+        let mut config = valid();
+        config.library.loan_index = config.library.user_index.clone();
+        assert_eq!(invalid_message(&config), "library.loan_index must be different from library.user_index");
+        config.library.loan_index = "pinjam.an".to_string();
+        assert_eq!(invalid_message(&config), "library.loan_index must be a lowercase index name without dots");
+
+        let mut config = valid();
+        config.library.default_page_size = 10_001;
+        assert_eq!(invalid_message(&config), "library.default_page_size must be between 1 and 10000");
+
+        let mut config = valid();
+        config.fines.genres.insert("Novel".to_string(), FineRule::default());
+        assert_eq!(invalid_message(&config), "fines.genres.Novel must be lowercase");
+        config.fines.genres = HashMap::from([("novel".to_string(), FineRule { daily_rate: Some(-1), ..Default::default() })]);
+        assert_eq!(invalid_message(&config), "fines.genres.novel cannot have negative values");
+    }
+
+    // Semua environment dicek di satu tes, supaya tes lain yang jalan bareng ga ikut kena
+    #[test]
+    fn apply_env() {
+        let vars = [
+            ("LIBRARY_PORT", " 8080 "),
+            ("LIBRARY_WORKERS", "4"),
+            ("LIBRARY_CORS_ORIGINS", "http://a.test, ,https://b.test"),
+            ("LIBRARY_DATABASE_BACKEND", "Memory"),
+            ("LIBRARY_AUTH_ENABLED", "false"),
+            ("LIBRARY_FINE_MAX_PER_ITEM", "50000")
+        ];
+        for (name, value) in vars {
+            env::set_var(name, value);
+        }
+        let mut config = Config::default();
+        let applied = config.apply_env();
+
+        env::set_var("LIBRARY_DATABASE_BACKEND", "mongo");
+        let backend = Config::default().apply_env();
+        env::set_var("LIBRARY_DATABASE_BACKEND", "memory");
+        env::set_var("LIBRARY_PORT", "delapan");
+        let port = Config::default().apply_env();
+        for (name, _) in vars {
+            env::remove_var(name);
+        }
+
+        assert!(applied.is_ok());
+        assert_eq!(config.server.port, 8080);
+        assert_eq!(config.server.workers, Some(4));
+        assert_eq!(config.server.cors_origins, ["http://a.test", "https://b.test"]);
+        assert!(config.database.backend == Backend::Memory);
+        assert!(!config.auth.enabled);
+        assert_eq!(config.fines.max_per_item, Some(50_000));
+        assert_eq!(config.server.bind_address, "127.0.0.1");
+
+        assert!(matches!(backend, Err(ConfigError::Env(name, _)) if name == "LIBRARY_DATABASE_BACKEND"));
+        assert!(matches!(port, Err(ConfigError::Env(name, _)) if name == "LIBRARY_PORT"));
+    }
+
+    #[test]
+    fn log_levels() {
+        assert!(is_log_level("info"));
+        assert!(is_log_level("WARN"));
+        assert!(is_log_level("actix_web=info, library_app=debug"));
+        assert!(is_log_level("info,library_app=trace"));
+        assert!(!is_log_level(""));
+        assert!(!is_log_level("verbose"));
+        assert!(!is_log_level("=info"));
+        assert!(!is_log_level("library_app=loud"));
+    }
+}
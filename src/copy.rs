@@ -0,0 +1,260 @@
+use std::collections::{HashMap, HashSet};
+use actix_web::{http::StatusCode, web::{self, Data}, HttpResponse};
+use chrono::Utc;
+use serde_json::{json, Value};
+use crate::{config::Config, hold::promote_next, loan::is_on_loan, mapping::book_index, store::LibraryStore, libs::*};
+use super::structs::*;
+
+/// Barcode cuma harus unik di satu library
+fn copy_id(user_id: &str, barcode: &str) -> String {
+    format!("{}:{}", user_id, barcode)
+}
+
+/// Indeks eksemplar harus selalu ada
+async fn create_copy_index(cfg: &Config, db: &dyn LibraryStore) -> Result<(), Errors> {
+    let body =
+        json!(
+            {
+                "mappings": {
+                    "properties": {
+                        "library": {"type": "keyword"},
+                        "genre": {"type": "keyword"},
+                        "book_id": {"type": "keyword"},
+                        "barcode": {"type": "keyword"},
+                        "condition": {"type": "keyword"},
+                        "location": {"type": "keyword"},
+                        "status": {"type": "keyword"},
+                        "added_at": {"type": "date"},
+                        "retired_at": {"type": "date"}
+                    }
+                }
+            }
+        );
+    create_index_if_missing(&cfg.library.copy_index, &body, db).await
+}
+
+/// Ambil satu eksemplar dari barcodenya
+pub async fn get_copy(user_id: &str, barcode: &str, cfg: &Config, db: &dyn LibraryStore) -> Result<Value, Errors> {
+    let response = db.get_single_document(&cfg.library.copy_index, &copy_id(user_id, barcode), None).await?;
+    if !response.status_code().is_success() {
+        return Err(store_error(&response, Errors::CopyNotFound(barcode.to_string())));
+    }
+    Ok(response.body)
+}
+
+/// Ganti status satu eksemplar
+pub async fn set_copy_status(user_id: &str, barcode: &str, status: CopyStatus, cfg: &Config, db: &dyn LibraryStore) -> Result<(), Errors> {
+    let response = db.update_single_document(&cfg.library.copy_index, &copy_id(user_id, barcode), &json!({"status": status})).await?;
+    if !response.status_code().is_success() {
+        return Err(store_error(&response, Errors::CopyNotFound(barcode.to_string())));
+    }
+    Ok(())
+}
+
+/// Hitung eksemplar satu buku yang belum dipensiunkan, id buku cuma unik di satu genre
+pub async fn count_copies(user_id: &str, genre: &str, book_id: &str, cfg: &Config, db: &dyn LibraryStore) -> Result<i64, Errors> {
+    let body = json!({
+        "query": {
+            "bool": {
+                "filter": [
+                    {"term": {"library": user_id}},
+                    {"term": {"genre": genre}},
+                    {"term": {"book_id": book_id}}
+                ],
+                "must_not": [{"term": {"status": CopyStatus::Retired}}]
+            }
+        }
+    });
+    let response = db.search(&cfg.library.copy_index, &body, None, Some(0)).await?;
+    match response.status_code() {
+        s if s.is_success() => Ok(response.body["hits"]["total"]["value"].as_i64().unwrap_or_default()),
+        StatusCode::NOT_FOUND => Ok(0),
+        _ => Err(store_error(&response, Errors::Unknown))
+    }
+}
+
+/// Jumlah eksemplar yang tersedia untuk tiap buku, kuncinya (genre, id buku) karena id buku cuma unik di satu genre
+pub async fn available_copies(user_id: &str, books: &[(String, String)], cfg: &Config, db: &dyn LibraryStore) -> Result<HashMap<(String, String), u64>, Errors> {
+    if books.is_empty() {
+        return Ok(HashMap::new());
+    }
+    let genres: HashSet<&String> = books.iter().map(|(g, _)| g).collect();
+    let book_ids: HashSet<&String> = books.iter().map(|(_, b)| b).collect();
+    let body = json!({
+        "query": {
+            "bool": {
+                "filter": [
+                    {"term": {"library": user_id}},
+                    {"terms": {"genre": genres}},
+                    {"terms": {"book_id": book_ids}},
+                    {"term": {"status": CopyStatus::Available}}
+                ]
+            }
+        },
+        "aggs": {
+            "genres": {
+                "terms": {"field": "genre", "size": genres.len()},
+                "aggs": {
+                    "available": {
+                        "terms": {"field": "book_id", "size": book_ids.len()}
+                    }
+                }
+            }
+        }
+    });
+    let response = db.search(&cfg.library.copy_index, &body, None, Some(0)).await?;
+    match response.status_code() {
+        s if s.is_success() => (),
+        StatusCode::NOT_FOUND => return Ok(HashMap::new()),
+        _ => return Err(store_error(&response, Errors::Unknown))
+    }
+
+    let mut available = HashMap::new();
+    for genre in response.body["aggregations"]["genres"]["buckets"].as_array().into_iter().flatten() {
+        for book in genre["available"]["buckets"].as_array().into_iter().flatten() {
+            if let (Some(g), Some(b), Some(n)) = (genre["key"].as_str(), book["key"].as_str(), book["doc_count"].as_u64()) {
+                available.insert((g.to_string(), b.to_string()), n);
+            }
+        }
+    }
+    Ok(available)
+}
+
+/// Tambah eksemplar baru untuk satu buku
+pub async fn add_copy(path: web::Path<UserBookID>, data: web::Json<CopyInput>, cfg: Data<Config>, db: Data::<dyn LibraryStore>) -> Result<HttpResponse, Errors> {
+    // Cek kalo user, genre dan bukunya ada
+    let genre = path.genre.to_lowercase();
+    check_userid_genre(&path.user_id, &genre, &cfg, db.get_ref()).await?;
+    get_book(&book_index(&path.user_id, &genre), &path.book_id, Some("judul".to_string()), db.get_ref()).await?;
+
+    let barcode = data.barcode.trim();
+    if barcode.is_empty() {
+        return Err(Errors::Validation("barcode cannot be empty".to_string()));
+    }
+
+    // Buku tanpa eksemplar yang lagi dipinjam harus dikembalikan dulu, kalo engga eksemplar barunya bisa dipinjam lagi
+    if is_on_loan(&path.user_id, &genre, &path.book_id, &cfg, db.get_ref()).await? {
+        return Err(Errors::BookOnLoan(path.book_id.to_string()));
+    }
+
+    // Simpan dengan id dari barcode, kalo barcodenya sudah dipakai elastic nolak dengan 409
+    create_copy_index(&cfg, db.get_ref()).await?;
+    let copy = json!({
+        "library": path.user_id,
+        "genre": genre,
+        "book_id": path.book_id,
+        "barcode": barcode,
+        "condition": data.condition,
+        "location": data.location,
+        "status": CopyStatus::Available,
+        "added_at": timestamp(Utc::now())
+    });
+    let response = db.create_document(&cfg.library.copy_index, &copy_id(&path.user_id, barcode), &copy).await?;
+    match response.status_code() {
+        s if s.is_success() => (),
+        StatusCode::CONFLICT => return Err(Errors::CopyExists(barcode.to_string())),
+        _ => return Err(store_error(&response, Errors::Unknown))
+    }
+
+    // Kalo ada yang antre, eksemplar baru langsung disiapkan untuk pemesan pertama
+    promote_next(&path.user_id, &genre, &path.book_id, Some(barcode), &cfg, db.get_ref()).await?;
+    Ok(HttpResponse::Created().json(get_copy(&path.user_id, barcode, &cfg, db.get_ref()).await?))
+}
+
+/// Ambil semua eksemplar dari satu buku
+pub async fn list_copies(path: web::Path<UserBookID>, cfg: Data<Config>, db: Data::<dyn LibraryStore>) -> Result<HttpResponse, Errors> {
+    let genre = path.genre.to_lowercase();
+    check_userid_genre(&path.user_id, &genre, &cfg, db.get_ref()).await?;
+    create_copy_index(&cfg, db.get_ref()).await?;
+
+    let body = json!({
+        "query": {
+            "bool": {
+                "filter": [
+                    {"term": {"library": path.user_id}},
+                    {"term": {"genre": genre}},
+                    {"term": {"book_id": path.book_id}}
+                ]
+            }
+        },
+        "sort": [{"barcode": "asc"}]
+    });
+    let response = db.search(&cfg.library.copy_index, &body, None, Some(10_000)).await?;
+    if !response.status_code().is_success() {
+        return Err(store_error(&response, Errors::Unknown));
+    }
+    let copies: Vec<&Value> = response.body["hits"]["hits"].as_array().map(|h| h.iter().map(|c| &c["_source"]).collect()).unwrap_or_default();
+    Ok(HttpResponse::Ok().json(copies))
+}
+
+/// Cari eksemplar dari barcodenya
+pub async fn get_copy_by_barcode(path: web::Path<UserBarcode>, cfg: Data<Config>, db: Data::<dyn LibraryStore>) -> Result<HttpResponse, Errors> {
+    // Cek kalo elastic hidup
+    check_server(db.get_ref()).await?;
+    Ok(HttpResponse::Ok().json(get_copy(&path.user_id, &path.barcode, &cfg, db.get_ref()).await?))
+}
+
+/// Ubah kondisi, lokasi atau status eksemplar
+pub async fn update_copy(path: web::Path<UserBarcode>, data: web::Json<CopyUpdate>, cfg: Data<Config>, db: Data::<dyn LibraryStore>) -> Result<HttpResponse, Errors> {
+    // Cek kalo elastic hidup
+    check_server(db.get_ref()).await?;
+
+    // Status dipinjam dan ditahan cuma lewat peminjaman dan antrean, pensiun cuma lewat hapus
+    if matches!(data.status, Some(CopyStatus::OnLoan | CopyStatus::OnHold | CopyStatus::Retired)) {
+        return Err(Errors::Validation("status can only be set to available, lost or repair".to_string()));
+    }
+    let mut copy = get_copy(&path.user_id, &path.barcode, &cfg, db.get_ref()).await?;
+    let locked = [CopyStatus::OnLoan, CopyStatus::OnHold, CopyStatus::Retired].iter().any(|s| copy["status"] == json!(s));
+    if data.status.is_some() && locked {
+        return Err(Errors::CopyNotAvailable(path.barcode.to_string()));
+    }
+
+    // Cuma yang diisi yang diubah
+    let mut changes = json!({});
+    if let Some(condition) = &data.condition {
+        changes["condition"] = json!(condition);
+    }
+    if let Some(location) = &data.location {
+        changes["location"] = json!(location);
+    }
+    if let Some(status) = data.status {
+        changes["status"] = json!(status);
+    }
+    let response = db.update_single_document(&cfg.library.copy_index, &copy_id(&path.user_id, &path.barcode), &changes).await?;
+    if !response.status_code().is_success() {
+        return Err(store_error(&response, Errors::CopyNotFound(path.barcode.to_string())));
+    }
+
+    for (k, v) in changes.as_object().into_iter().flatten() {
+        copy[k] = v.clone();
+    }
+
+    // Eksemplar yang balik tersedia langsung disiapkan untuk antrean kalo ada
+    if data.status == Some(CopyStatus::Available) {
+        let (genre, book_id) = (copy["genre"].as_str().unwrap_or_default(), copy["book_id"].as_str().unwrap_or_default());
+        promote_next(&path.user_id, genre, book_id, Some(&path.barcode), &cfg, db.get_ref()).await?;
+        copy = get_copy(&path.user_id, &path.barcode, &cfg, db.get_ref()).await?;
+    }
+    Ok(HttpResponse::Ok().json(copy))
+}
+
+/// Pensiunkan eksemplar, datanya tetap disimpan untuk riwayat
+pub async fn retire_copy(path: web::Path<UserBarcode>, cfg: Data<Config>, db: Data::<dyn LibraryStore>) -> Result<HttpResponse, Errors> {
+    // Cek kalo elastic hidup
+    check_server(db.get_ref()).await?;
+
+    // Eksemplar yang masih dipinjam atau ditahan untuk pemesan harus dilepas dulu
+    let mut copy = get_copy(&path.user_id, &path.barcode, &cfg, db.get_ref()).await?;
+    if copy["status"] == json!(CopyStatus::OnLoan) || copy["status"] == json!(CopyStatus::OnHold) {
+        return Err(Errors::CopyNotAvailable(path.barcode.to_string()));
+    }
+
+    let changes = json!({"status": CopyStatus::Retired, "retired_at": timestamp(Utc::now())});
+    let response = db.update_single_document(&cfg.library.copy_index, &copy_id(&path.user_id, &path.barcode), &changes).await?;
+    if !response.status_code().is_success() {
+        return Err(store_error(&response, Errors::CopyNotFound(path.barcode.to_string())));
+    }
+    copy["status"] = changes["status"].clone();
+    copy["retired_at"] = changes["retired_at"].clone();
+    Ok(HttpResponse::Ok().json(copy))
+}
@@ -0,0 +1,110 @@
+use actix_web::http::StatusCode;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use crate::{config::Config, idempotency::fingerprint, store::{LibraryStore, StoreResponse}, structs::{BookSearchQuery, FieldError}, libs::*};
+
+/// Isi cursor: point in time yang dipakai, posisi hasil terakhir dan sidik pencariannya
+#[derive(Serialize, Deserialize)]
+struct Cursor {
+    pit: String,
+    after: Value,
+    print: String
+}
+
+/// Batas from + count di elastic (index.max_result_window), lebih dalam dari itu harus pakai cursor
+const MAX_RESULT_WINDOW: i64 = 10_000;
+
+fn invalid(field: &str, message: &str) -> Errors {
+    Errors::InvalidFields(vec![FieldError::new(field, message)])
+}
+
+fn encode(cursor: &Cursor) -> String {
+    URL_SAFE_NO_PAD.encode(json!(cursor).to_string())
+}
+
+fn decode(raw: &str) -> Result<Cursor, Errors> {
+    URL_SAFE_NO_PAD.decode(raw.trim())
+        .ok()
+        .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+        .ok_or_else(|| invalid("cursor", "is not a valid cursor"))
+}
+
+/// Pakai cursor kalo dikirim cursor atau use_cursor, from ga bisa dipakai bareng cursor
+pub fn wants_cursor(query: &BookSearchQuery) -> Result<bool, Errors> {
+    let wanted = query.cursor.is_some() || query.use_cursor.unwrap_or(false);
+    if wanted && query.from.is_some() {
+        return Err(invalid("from", "cannot be used with a cursor"));
+    }
+    Ok(wanted)
+}
+
+/// Paginasi biasa pakai from dan count, dicek di sini supaya elastic ga jawab eror 400 yang umum
+pub fn check_window(from: Option<i64>, count: i64) -> Result<(), Errors> {
+    let mut errors = vec![];
+    if from.map(|f| f < 0).unwrap_or(false) {
+        errors.push(FieldError::new("from", "must not be negative"));
+    }
+    if count < 0 {
+        errors.push(FieldError::new("count", "must not be negative"));
+    }
+    if errors.is_empty() && from.unwrap_or(0) + count > MAX_RESULT_WINDOW {
+        errors.push(FieldError::new("from", format!("from + count must be at most {}, use use_cursor=true for deeper pages", MAX_RESULT_WINDOW)));
+    }
+    if !errors.is_empty() {
+        return Err(Errors::InvalidFields(errors));
+    }
+    Ok(())
+}
+
+/// Ambil satu halaman lewat point in time dan search_after, hasilnya respons pencarian dan cursor halaman berikutnya.
+/// Halaman pertama membuka point in time, halaman yang kurang dari count berarti sudah habis dan point in time ditutup
+pub async fn search_page(index: &str, body: &Value, query: &BookSearchQuery, count: i64, cfg: &Config, db: &dyn LibraryStore) -> Result<(StoreResponse, Option<String>), Errors> {
+    if !(1..=MAX_RESULT_WINDOW).contains(&count) {
+        return Err(invalid("count", &format!("must be between 1 and {} when using a cursor", MAX_RESULT_WINDOW)));
+    }
+
+    // Cursor cuma bisa dipakai untuk pencarian yang sama
+    let print = fingerprint(&[index.as_bytes(), body.to_string().as_bytes()]);
+    let keep_alive = format!("{}m", cfg.library.cursor_minutes);
+    let (pit, after) = match &query.cursor {
+        Some(raw) => {
+            let cursor = decode(raw)?;
+            if cursor.print != print {
+                return Err(invalid("cursor", "belongs to a different search, send the same parameters as the first page"));
+            }
+            (cursor.pit, Some(cursor.after))
+        },
+        None => {
+            let response = db.open_point_in_time(index, &keep_alive).await?;
+            if !response.status_code().is_success() {
+                return Err(store_error(&response, Errors::Unknown));
+            }
+            (response.body["id"].as_str().unwrap_or_default().to_string(), None)
+        }
+    };
+
+    let mut body = body.clone();
+    body["pit"] = json!({"id": pit, "keep_alive": keep_alive});
+    if let Some(after) = after {
+        body["search_after"] = after;
+    }
+    let response = db.search_point_in_time(&body, Some(count)).await?;
+    match response.status_code() {
+        s if s.is_success() => (),
+        StatusCode::NOT_FOUND => return Err(Errors::CursorExpired),
+        _ => return Err(store_error(&response, Errors::Unknown))
+    }
+
+    // Id point in time bisa berubah tiap pencarian, yang terbaru yang dipakai
+    let pit = response.body["pit_id"].as_str().map(str::to_string).unwrap_or(pit);
+    let hits = response.body["hits"]["hits"].as_array().cloned().unwrap_or_default();
+    let next = match hits.last() {
+        Some(last) if hits.len() as i64 >= count => Some(encode(&Cursor { pit, after: last["sort"].clone(), print })),
+        _ => {
+            db.close_point_in_time(&pit).await?;
+            None
+        }
+    };
+    Ok((response, next))
+}
@@ -1,112 +1,299 @@
+use std::{fs, sync::{Arc, atomic::{AtomicUsize, Ordering}}};
+use async_trait::async_trait;
 use elasticsearch::{
-    http::{transport::Transport, response::Response}, 
-    indices::{IndicesCreateParts, IndicesDeleteParts}, 
+    auth::Credentials,
+    cert::{Certificate, CertificateValidation},
+    http::{
+        headers::{HeaderValue, AUTHORIZATION},
+        transport::{Connection, ConnectionPool, SingleNodeConnectionPool, TransportBuilder},
+        response::Response,
+        Url
+    },
+    indices::{IndicesCreateParts, IndicesDeleteParts, IndicesGetMappingParts, IndicesPutIndexTemplateParts},
     cat::CatIndicesParts,
+    params::Refresh,
     *
 };
-use serde::Serialize;
-use serde_json::json;
+use serde_json::{json, Value};
+use thiserror::Error;
+use crate::{config::DatabaseConfig, store::{LibraryStore, StoreResponse}};
+
+#[derive(Error, Debug)]
+pub enum ConnectionError {
+    #[error("Invalid database url {0}: {1}")]
+    Url(String, String),
+    #[error("Cannot read CA certificate {0}: {1}")]
+    Certificate(String, String),
+    #[error("Invalid database API key")]
+    ApiKey,
+    #[error("Cannot build database client: {0}")]
+    Build(String)
+}
+
+/// Pool untuk banyak node, node dipakai bergantian (round robin)
+#[derive(Debug, Clone)]
+pub struct MultiNodeConnectionPool {
+    connections: Vec<Connection>,
+    next: Arc<AtomicUsize>
+}
+
+impl MultiNodeConnectionPool {
+    pub fn new(urls: Vec<Url>) -> Self {
+        Self {
+            connections: urls.into_iter().map(Connection::new).collect(),
+            next: Arc::new(AtomicUsize::new(0))
+        }
+    }
+}
+
+impl ConnectionPool for MultiNodeConnectionPool {
+    fn next(&self) -> &Connection {
+        let i = self.next.fetch_add(1, Ordering::Relaxed);
+        &self.connections[i % self.connections.len()]
+    }
+}
 
 pub struct Database {
     pub es: Elasticsearch
 }
 
 impl Database {
-    pub fn new(url: &str) -> Self {
-        Self{
-            es: Elasticsearch::new(Transport::single_node(url).unwrap())
+    /// Buat koneksi ke elastic sesuai konfigurasi, satu atau banyak node, dengan auth dan sertifikat CA kalo ada
+    pub fn new(config: &DatabaseConfig) -> Result<Self, ConnectionError> {
+        let urls = config.urls.iter()
+            .map(|u| Url::parse(u).map_err(|e| ConnectionError::Url(u.clone(), e.to_string())))
+            .collect::<Result<Vec<Url>, _>>()?;
+
+        let mut builder = match urls.len() {
+            1 => TransportBuilder::new(SingleNodeConnectionPool::new(urls[0].clone())),
+            _ => TransportBuilder::new(MultiNodeConnectionPool::new(urls))
+        };
+
+        // Auth pakai username/password atau API key, API key bisa "id:key" atau yang sudah di-encode
+        if let (Some(username), Some(password)) = (&config.username, &config.password) {
+            builder = builder.auth(Credentials::Basic(username.clone(), password.clone()));
+        }
+        if let Some(key) = &config.api_key {
+            builder = match key.split_once(':') {
+                Some((id, secret)) => builder.auth(Credentials::ApiKey(id.to_string(), secret.to_string())),
+                None => builder.header(AUTHORIZATION, HeaderValue::from_str(&format!("ApiKey {}", key)).map_err(|_| ConnectionError::ApiKey)?)
+            };
         }
+
+        // Sertifikat CA sendiri untuk cluster dengan TLS
+        if let Some(path) = &config.ca_cert {
+            let pem = fs::read(path).map_err(|e| ConnectionError::Certificate(path.clone(), e.to_string()))?;
+            let cert = Certificate::from_pem(&pem).map_err(|e| ConnectionError::Certificate(path.clone(), e.to_string()))?;
+            builder = builder.cert_validation(CertificateValidation::Full(cert));
+        } else if !config.verify_certificate {
+            builder = builder.cert_validation(CertificateValidation::None);
+        }
+
+        let transport = builder.build().map_err(|e| ConnectionError::Build(e.to_string()))?;
+        Ok(Self{
+            es: Elasticsearch::new(transport)
+        })
     }
+}
 
+/// Ubah respons elastic jadi status dan body json, body kosong jadi null
+async fn to_store_response(response: Response) -> Result<StoreResponse, Error> {
+    let status = response.status_code();
+    let text = response.text().await?;
+    let body = serde_json::from_str(&text).unwrap_or(Value::Null);
+    Ok(StoreResponse::new(status, body))
+}
+
+#[async_trait]
+impl LibraryStore for Database {
     /// Buat Dokumen Baru secara banyak
-    pub async fn index_documents(&self, index: &str, data: &[impl Serialize]) -> Result<Response, Error> {
-        
-        // Konversi ke bentuk yang diminta elastic
+    async fn index_documents(&self, index: &str, data: &[(Option<String>, Value)]) -> Result<StoreResponse, Error> {
+
+        // Konversi ke bentuk yang diminta elastic, yang ada idnya pakai create supaya ga menimpa
         let body: Vec<BulkOperation<_>> = data
             .iter()
-            .map(|p| {
-                BulkOperation::index(p).into()
+            .map(|(id, p)| match id {
+                Some(id) => BulkOperation::create(id, p).into(),
+                None => BulkOperation::index(p).into()
             })
             .collect();
 
         // Kirim ke elastic
-        self.es
+        let response = self.es
             .bulk(BulkParts::Index(index))
             .body(body)
             .send()
-            .await
+            .await?;
+        to_store_response(response).await
     }
 
     /// Cari dokumen di indeks
-    pub async fn search(&self, index: &str, body: impl Serialize, from: Option<i64>, count: Option<i64>) -> Result<Response, Error>{
+    async fn search(&self, index: &str, body: &Value, from: Option<i64>, count: Option<i64>) -> Result<StoreResponse, Error>{
 
         // Cari di indeks dengan paginasi dan data pencarian
-        self.es
+        let response = self.es
             .search(SearchParts::Index(&[index]))
             .from(from.unwrap_or(0))
             .size(count.unwrap_or(20))
             .body(body)
             .send()
-            .await
+            .await?;
+        to_store_response(response).await
+    }
+
+    /// Buka point in time di indeks
+    async fn open_point_in_time(&self, index: &str, keep_alive: &str) -> Result<StoreResponse, Error> {
+        let response = self.es
+            .open_point_in_time(OpenPointInTimeParts::Index(&[index]))
+            .keep_alive(keep_alive)
+            .send()
+            .await?;
+        to_store_response(response).await
+    }
+
+    /// Cari lewat point in time, indeksnya sudah ikut di pit jadi ga disebut lagi
+    async fn search_point_in_time(&self, body: &Value, count: Option<i64>) -> Result<StoreResponse, Error> {
+        let response = self.es
+            .search(SearchParts::None)
+            .size(count.unwrap_or(20))
+            .body(body)
+            .send()
+            .await?;
+        to_store_response(response).await
+    }
+
+    /// Tutup point in time
+    async fn close_point_in_time(&self, id: &str) -> Result<StoreResponse, Error> {
+        let response = self.es
+            .close_point_in_time()
+            .body(json!({"id": id}))
+            .send()
+            .await?;
+        to_store_response(response).await
+    }
+
+    /// Buat satu dokumen dengan id tertentu, langsung bisa dicari setelahnya
+    async fn create_document(&self, index: &str, doc_id: &str, data: &Value) -> Result<StoreResponse, Error> {
+        let response = self.es
+            .create(CreateParts::IndexId(index, doc_id))
+            .refresh(Refresh::WaitFor)
+            .body(data)
+            .send()
+            .await?;
+        to_store_response(response).await
+    }
+
+    /// Simpan satu dokumen dengan id tertentu
+    async fn put_document(&self, index: &str, doc_id: &str, data: &Value) -> Result<StoreResponse, Error> {
+        let response = self.es
+            .index(IndexParts::IndexId(index, doc_id))
+            .refresh(Refresh::WaitFor)
+            .body(data)
+            .send()
+            .await?;
+        to_store_response(response).await
     }
 
     /// Ambil satu dokumen dari indeks
-    pub async fn get_single_document(&self, index: &str, doc_id: &str, retrieve_fields: Option<String>) -> Result<Response, Error>{
-        
+    async fn get_single_document(&self, index: &str, doc_id: &str, retrieve_fields: Option<String>) -> Result<StoreResponse, Error>{
+
         // Apa aja yang mau diambil dari dokumennya
         let fields_to_return = retrieve_fields.unwrap_or("*".to_string());
 
         // Minta ke elastic untuk dokumennya
-        self.es
+        let response = self.es
             .get_source(GetSourceParts::IndexId(index, doc_id))
             ._source_includes(&[&fields_to_return])
             .send()
-            .await
+            .await?;
+        to_store_response(response).await
     }
 
     // Update satu dokumen
-    pub async fn update_single_document(&self, index: &str, document_id: &str, data: impl Serialize) -> Result<Response, Error> {
-        self.es
+    async fn update_single_document(&self, index: &str, document_id: &str, data: &Value) -> Result<StoreResponse, Error> {
+        let response = self.es
             .update(UpdateParts::IndexId(index, document_id))
             .body(json!({"doc": data}))
             .send()
-            .await
+            .await?;
+        to_store_response(response).await
     }
 
     // Hapus satu dokumen
-    pub async fn delete_single_document(&self, index: &str, document_id: &str) -> Result<Response, Error>{
-        self.es
+    async fn delete_single_document(&self, index: &str, document_id: &str) -> Result<StoreResponse, Error>{
+        let response = self.es
             .delete(DeleteParts::IndexId(index, document_id))
             .send()
-            .await
+            .await?;
+        to_store_response(response).await
     }
 
     // Buat satu indeks baru
-    pub async fn create_single_index(&self, index: &str, body: &impl Serialize) -> Result<Response, Error>{
-        self.es
+    async fn create_single_index(&self, index: &str, body: &Value) -> Result<StoreResponse, Error>{
+        let response = self.es
             .indices()
             .create(IndicesCreateParts::Index(index))
             .body(body)
             .send()
-            .await
+            .await?;
+        to_store_response(response).await
     }
 
     // Ambil data statistik satu atau lebih indeks
-    pub async fn get_indices(&self, index: Option<String>) -> Result<Response, Error>{
-        self.es
+    async fn get_indices(&self, index: Option<String>) -> Result<StoreResponse, Error>{
+        let response = self.es
             .cat()
             .indices(CatIndicesParts::Index(&[&index.unwrap_or("*".to_string())]))
             .format("json")
             .send()
-            .await
+            .await?;
+        to_store_response(response).await
     }
-    
+
     // Hapus satu indeks
-    pub async fn delete_single_index(&self, index: String) -> Result<Response, Error>{
-        self.es
+    async fn delete_single_index(&self, index: String) -> Result<StoreResponse, Error>{
+        let response = self.es
             .indices()
             .delete(IndicesDeleteParts::Index(&[&index]))
             .send()
-            .await
+            .await?;
+        to_store_response(response).await
     }
-}
\ No newline at end of file
+
+    // Simpan index template
+    async fn put_index_template(&self, name: &str, body: &Value) -> Result<StoreResponse, Error> {
+        let response = self.es
+            .indices()
+            .put_index_template(IndicesPutIndexTemplateParts::Name(name))
+            .body(body)
+            .send()
+            .await?;
+        to_store_response(response).await
+    }
+
+    // Ambil mapping satu indeks
+    async fn get_mapping(&self, index: &str) -> Result<StoreResponse, Error> {
+        let response = self.es
+            .indices()
+            .get_mapping(IndicesGetMappingParts::Index(&[index]))
+            .send()
+            .await?;
+        to_store_response(response).await
+    }
+
+    // Salin dokumen antar indeks, ditunggu sampai selesai
+    async fn reindex(&self, source: &str, dest: &str) -> Result<StoreResponse, Error> {
+        let response = self.es
+            .reindex()
+            .wait_for_completion(true)
+            .refresh(true)
+            .body(json!({
+                "conflicts": "proceed",
+                "source": {"index": source},
+                "dest": {"index": dest, "op_type": "create"}
+            }))
+            .send()
+            .await?;
+        to_store_response(response).await
+    }
+}
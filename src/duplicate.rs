@@ -0,0 +1,88 @@
+use std::collections::HashMap;
+
+use serde_json::{json, Value};
+use crate::{mapping::user_books_prefix, store::LibraryStore, libs::*};
+
+/// Berapa buku yang dicek dalam satu kali cari
+const CHUNK: usize = 200;
+
+/// Rapikan teks supaya beda huruf besar dan spasi ga dianggap beda
+fn clean(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ").to_lowercase()
+}
+
+fn isbn_key(book: &Value) -> Option<String> {
+    book["isbn"].as_str().filter(|i| !i.trim().is_empty()).map(|i| format!("isbn:{}", i.trim()))
+}
+
+fn title_key(book: &Value) -> Option<String> {
+    let title = book["judul"].as_str().map(clean).filter(|t| !t.is_empty())?;
+    let author = book["penulis"].as_str().map(clean).filter(|a| !a.is_empty())?;
+    Some(format!("title:{}|{}", title, author))
+}
+
+/// Kunci duplikat buku yang masuk: isbn yang sudah dirapikan, kalo ga ada pakai judul dan penulis.
+/// Buku tanpa isbn dan tanpa judul atau penulis ga bisa dicek
+pub fn book_key(book: &Value) -> Option<String> {
+    isbn_key(book).or_else(|| title_key(book))
+}
+
+/// Semua kunci buku yang sudah ada, isbn dan judul dengan penulis,
+/// supaya buku baru tanpa isbn tetap ketemu dengan buku lama yang punya isbn
+pub fn known_keys(book: &Value) -> Vec<String> {
+    isbn_key(book).into_iter().chain(title_key(book)).collect()
+}
+
+/// Cari buku yang sudah ada di semua genre user, hasilnya kunci -> (genre, id buku).
+/// Kalo ada beberapa yang cocok, yang pertama ketemu yang dipakai
+pub async fn find_existing(user_id: &str, books: &[Value], db: &dyn LibraryStore) -> Result<HashMap<String, (String, String)>, Errors> {
+    let prefix = user_books_prefix(user_id);
+    let mut found = HashMap::new();
+
+    for chunk in books.chunks(CHUNK) {
+        // Isbn dicari sekaligus, judul dan penulis dicari per buku lalu dicocokkan lagi di bawah
+        let isbns: Vec<&str> = chunk.iter().filter_map(|b| b["isbn"].as_str()).filter(|i| !i.trim().is_empty()).collect();
+        let mut should = vec![];
+        if !isbns.is_empty() {
+            should.push(json!({"terms": {"isbn": isbns}}));
+        }
+        for book in chunk.iter().filter(|b| b["isbn"].as_str().map(|i| i.trim().is_empty()).unwrap_or(true)) {
+            if let (Some(title), Some(author)) = (book["judul"].as_str(), book["penulis"].as_str()) {
+                should.push(json!({
+                    "bool": {
+                        "must": [
+                            {"match_phrase": {"judul": title}},
+                            {"match_phrase": {"penulis": author}}
+                        ]
+                    }
+                }));
+            }
+        }
+        if should.is_empty() {
+            continue;
+        }
+
+        let body = json!({
+            "_source": ["isbn", "judul", "penulis"],
+            "query": {
+                "bool": {
+                    "should": should,
+                    "minimum_should_match": 1
+                }
+            }
+        });
+        let response = db.search(&format!("{}*", prefix), &body, None, Some(10_000)).await?;
+        if !response.status_code().is_success() {
+            return Err(store_error(&response, Errors::Unknown));
+        }
+
+        for hit in response.body["hits"]["hits"].as_array().into_iter().flatten() {
+            let genre = hit["_index"].as_str().unwrap_or_default().strip_prefix(&prefix).unwrap_or_default().to_string();
+            let id = hit["_id"].as_str().unwrap_or_default().to_string();
+            for key in known_keys(&hit["_source"]) {
+                found.entry(key).or_insert((genre.clone(), id.clone()));
+            }
+        }
+    }
+    Ok(found)
+}
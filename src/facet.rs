@@ -0,0 +1,78 @@
+use serde_json::{json, Map, Value};
+use crate::{structs::{BookSearchQuery, FieldError}, validate::parse_fields, libs::*};
+
+/// Facet yang bisa dihitung, genre dihitung dari field genre di bukunya, bukan dari indeks genre
+pub const FACETS: [&str; 5] = ["penulis", "penerbit", "bahasa", "genre", "tahun"];
+
+/// Paling banyak nilai per facet
+const MAX_FACET_SIZE: i64 = 100;
+
+/// Agregasi untuk facet yang diminta, None kalo ga ada yang diminta
+pub fn facet_aggs(query: &BookSearchQuery) -> Result<Option<Value>, Errors> {
+    let names = match &query.facets {
+        Some(f) => parse_fields("facets", f, &FACETS)?,
+        None => return Ok(None)
+    };
+    let size = match query.facet_size {
+        Some(n) if !(1..=MAX_FACET_SIZE).contains(&n) => return Err(Errors::InvalidFields(vec![
+            FieldError::new("facet_size", format!("must be between 1 and {}", MAX_FACET_SIZE))
+        ])),
+        Some(n) => n,
+        None => 10
+    };
+
+    let mut aggs = Map::new();
+    for name in names {
+        let agg = match name.as_str() {
+            "penulis" | "penerbit" => json!({"terms": {"field": format!("{}.keyword", name), "size": size}}),
+            "bahasa" | "genre" => json!({"terms": {"field": name, "size": size}}),
+            _ => json!({
+                "date_histogram": {
+                    "field": "tanggal_terbit",
+                    "calendar_interval": "year",
+                    "format": "yyyy",
+                    "min_doc_count": 1
+                }
+            })
+        };
+        aggs.insert(name, agg);
+    }
+    Ok(Some(aggs.into()))
+}
+
+/// Nilai facet yang dipilih jadi filter. Genre buku dikirim lewat book_genre karena genre dipakai buat milih indeks
+pub fn facet_filters(query: &BookSearchQuery) -> Vec<Value> {
+    let mut filters = vec![];
+    for (field, value) in [("penulis.keyword", &query.penulis), ("penerbit.keyword", &query.penerbit), ("bahasa", &query.bahasa), ("genre", &query.book_genre)] {
+        if let Some(v) = value {
+            filters.push(json!({"term": {field: v}}));
+        }
+    }
+    if let Some(year) = query.tahun {
+        filters.push(json!({
+            "range": {
+                "tanggal_terbit": {
+                    "gte": format!("01-01-{:04}", year),
+                    "lte": format!("31-12-{:04}", year),
+                    "format": "dd-MM-yyyy"
+                }
+            }
+        }));
+    }
+    filters
+}
+
+/// Ubah hasil agregasi jadi daftar nilai dan jumlah bukunya
+pub fn read_facets(aggregations: &Value) -> Value {
+    let facets: Map<String, Value> = aggregations.as_object().into_iter().flatten().map(|(name, agg)| {
+        let buckets: Vec<Value> = agg["buckets"].as_array().into_iter().flatten().map(|b| {
+            let value = match name.as_str() {
+                "tahun" => json!(b["key_as_string"]),
+                _ => b["key"].clone()
+            };
+            json!({"value": value, "count": b["doc_count"]})
+        }).collect();
+        (name.clone(), json!(buckets))
+    }).collect();
+    facets.into()
+}
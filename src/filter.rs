@@ -0,0 +1,96 @@
+use chrono::NaiveDate;
+use serde_json::{json, Value};
+use crate::{facet::facet_filters, isbn::normalize, structs::{BookSearchQuery, FieldError}, validate::{parse_fields, RETURN_FIELDS}, libs::*};
+
+/// Tanggal batas harus dd-MM-yyyy seperti tanggal_terbit
+fn check_date(field: &str, value: &Option<String>, errors: &mut Vec<FieldError>) -> Option<NaiveDate> {
+    let value = value.as_deref()?;
+    match NaiveDate::parse_from_str(value, "%d-%m-%Y").ok().filter(|_| value.len() == 10) {
+        Some(d) => Some(d),
+        None => {
+            errors.push(FieldError::new(field, "must be a valid date in dd-MM-yyyy format"));
+            None
+        }
+    }
+}
+
+/// Satu range, batas yang ga diisi ga dikirim
+fn range(field: &str, from: Option<Value>, to: Option<Value>) -> Option<Value> {
+    if from.is_none() && to.is_none() {
+        return None;
+    }
+    let mut bounds = json!({});
+    if let Some(f) = from {
+        bounds["gte"] = f;
+    }
+    if let Some(t) = to {
+        bounds["lte"] = t;
+    }
+    if field == "tanggal_terbit" {
+        bounds["format"] = json!("dd-MM-yyyy");
+    }
+    Some(json!({"range": {field: bounds}}))
+}
+
+/// Semua filter pencarian, dipakai di filter context jadi ga mengubah skor.
+/// Kalo ada yang salah semua field yang salah dikirim sekaligus
+pub fn search_filters(query: &BookSearchQuery) -> Result<Vec<Value>, Errors> {
+    let mut errors = vec![];
+    let mut filters = facet_filters(query);
+
+    // Judul yang sama persis, beda dari search_term yang dicari per kata
+    if let Some(judul) = &query.judul {
+        filters.push(json!({"term": {"judul.keyword": judul}}));
+    }
+
+    // Isbn dicocokkan setelah dirapikan, jadi ISBN-10 dan yang pakai tanda hubung juga ketemu
+    if let Some(isbn) = &query.isbn {
+        match normalize(isbn) {
+            Ok(isbn) => filters.push(json!({"term": {"isbn": isbn}})),
+            Err(e) => errors.push(FieldError::new("isbn", e))
+        }
+    }
+
+    // Range jumlah halaman
+    let (min, max) = (query.jumlah_halaman_min, query.jumlah_halaman_max);
+    if min.map(|m| m < 0).unwrap_or(false) || max.map(|m| m < 0).unwrap_or(false) {
+        errors.push(FieldError::new("jumlah_halaman", "bounds must not be negative"));
+    } else if let (Some(min), Some(max)) = (min, max) {
+        if min > max {
+            errors.push(FieldError::new("jumlah_halaman_min", "must not be more than jumlah_halaman_max"));
+        }
+    }
+    filters.extend(range("jumlah_halaman", min.map(|m| json!(m)), max.map(|m| json!(m))));
+
+    // Range tanggal terbit
+    let from = check_date("tanggal_terbit_from", &query.tanggal_terbit_from, &mut errors);
+    let to = check_date("tanggal_terbit_to", &query.tanggal_terbit_to, &mut errors);
+    if let (Some(f), Some(t)) = (from, to) {
+        if f > t {
+            errors.push(FieldError::new("tanggal_terbit_from", "must not be after tanggal_terbit_to"));
+        }
+    }
+    filters.extend(range("tanggal_terbit", from.and(query.tanggal_terbit_from.clone()).map(Value::from), to.and(query.tanggal_terbit_to.clone()).map(Value::from)));
+
+    // Field yang harus ada dan yang harus kosong
+    for (param, list, missing) in [("exists", &query.exists, false), ("missing", &query.missing, true)] {
+        let fields = match list.as_deref().map(|l| parse_fields(param, l, &RETURN_FIELDS)) {
+            Some(Ok(f)) => f,
+            Some(Err(Errors::InvalidFields(e))) => {
+                errors.extend(e);
+                continue;
+            },
+            Some(Err(e)) => return Err(e),
+            None => continue
+        };
+        for field in fields {
+            let exists = json!({"exists": {"field": field}});
+            filters.push(if missing { json!({"bool": {"must_not": exists}}) } else { exists });
+        }
+    }
+
+    if !errors.is_empty() {
+        return Err(Errors::InvalidFields(errors));
+    }
+    Ok(filters)
+}
@@ -0,0 +1,262 @@
+use actix_web::{http::StatusCode, web::{self, Data}, HttpResponse};
+use chrono::{DateTime, Utc};
+use serde_json::{json, Value};
+use crate::{config::Config, store::LibraryStore, libs::*};
+use super::structs::*;
+
+/// Indeks denda harus selalu ada
+async fn create_fine_index(cfg: &Config, db: &dyn LibraryStore) -> Result<(), Errors> {
+    let body =
+        json!(
+            {
+                "mappings": {
+                    "properties": {
+                        "library": {"type": "keyword"},
+                        "borrower": {"type": "keyword"},
+                        "kind": {"type": "keyword"},
+                        "amount": {"type": "long"},
+                        "loan_id": {"type": "keyword"},
+                        "genre": {"type": "keyword"},
+                        "book_id": {"type": "keyword"},
+                        "days_late": {"type": "integer"},
+                        "created_at": {"type": "date"}
+                    }
+                }
+            }
+        );
+    create_index_if_missing(&cfg.library.fine_index, &body, db).await
+}
+
+/// Hitung denda telat sesuai aturan genre, hasilnya (hari telat, denda)
+fn late_fine(genre: &str, due_at: DateTime<Utc>, returned_at: DateTime<Utc>, cfg: &Config) -> (i64, i64) {
+    let late = returned_at - due_at;
+    if late.num_seconds() <= 0 {
+        return (0, 0);
+    }
+
+    // Telat sebagian hari dihitung satu hari
+    let days_late = (late.num_seconds() + 86_399) / 86_400;
+    let (daily_rate, grace_days, max_per_item) = cfg.fines.rule_for(genre);
+    let amount = (days_late - grace_days).max(0).saturating_mul(daily_rate);
+    (days_late, max_per_item.map(|m| amount.min(m)).unwrap_or(amount))
+}
+
+/// Catat denda untuk peminjaman yang kembalinya telat, hasilnya besar dendanya.
+/// Idnya ikut id peminjaman supaya kalo pengembalian diulang dendanya ga dobel
+pub async fn charge_late_return(loan_id: &str, loan: &Value, returned_at: DateTime<Utc>, cfg: &Config, db: &dyn LibraryStore) -> Result<i64, Errors> {
+    let due_at = match loan["due_at"].as_str().and_then(|d| DateTime::parse_from_rfc3339(d).ok()) {
+        Some(d) => d.with_timezone(&Utc),
+        None => return Ok(0)
+    };
+    let genre = loan["genre"].as_str().unwrap_or_default();
+    let (days_late, amount) = late_fine(genre, due_at, returned_at, cfg);
+    if amount == 0 {
+        return Ok(0);
+    }
+
+    create_fine_index(cfg, db).await?;
+    let charge = json!({
+        "library": loan["library"],
+        "borrower": loan["borrower"],
+        "kind": LedgerKind::Charge,
+        "amount": amount,
+        "loan_id": loan_id,
+        "genre": genre,
+        "book_id": loan["book_id"],
+        "days_late": days_late,
+        "created_at": timestamp(returned_at)
+    });
+    let response = db.create_document(&cfg.library.fine_index, &format!("{}:fine", loan_id), &charge).await?;
+    if !response.status_code().is_success() && response.status_code() != StatusCode::CONFLICT {
+        return Err(store_error(&response, Errors::Unknown));
+    }
+    Ok(amount)
+}
+
+/// Semua catatan denda satu peminjam, yang terbaru duluan
+async fn ledger(user_id: &str, borrower: &str, cfg: &Config, db: &dyn LibraryStore) -> Result<Vec<(String, Value)>, Errors> {
+    let body = json!({
+        "query": {
+            "bool": {
+                "filter": [
+                    {"term": {"library": user_id}},
+                    {"term": {"borrower": borrower}}
+                ]
+            }
+        },
+        "sort": [{"created_at": "desc"}]
+    });
+    let response = db.search(&cfg.library.fine_index, &body, None, Some(10_000)).await?;
+    match response.status_code() {
+        s if s.is_success() => (),
+        StatusCode::NOT_FOUND => return Ok(vec![]),
+        _ => return Err(store_error(&response, Errors::Unknown))
+    }
+    Ok(response.body["hits"]["hits"].as_array().map(|hits| hits.iter().map(|h| {
+        (h["_id"].as_str().unwrap_or_default().to_string(), h["_source"].clone())
+    }).collect()).unwrap_or_default())
+}
+
+/// Jumlahkan catatan per jenis, hasilnya (denda, bayar, dihapuskan)
+fn totals(entries: &[(String, Value)]) -> (i64, i64, i64) {
+    let sum = |kind: LedgerKind| entries.iter()
+        .filter(|(_, e)| e["kind"] == json!(kind))
+        .map(|(_, e)| e["amount"].as_i64().unwrap_or_default())
+        .sum::<i64>();
+    (sum(LedgerKind::Charge), sum(LedgerKind::Payment), sum(LedgerKind::Waiver))
+}
+
+/// Sisa denda yang belum dibayar
+pub async fn balance(user_id: &str, borrower: &str, cfg: &Config, db: &dyn LibraryStore) -> Result<i64, Errors> {
+    let (charges, payments, waivers) = totals(&ledger(user_id, borrower, cfg, db).await?);
+    Ok(charges - payments - waivers)
+}
+
+/// Peminjam dengan sisa denda di atas batas tidak boleh pinjam lagi
+pub async fn check_fines(user_id: &str, borrower: &str, cfg: &Config, db: &dyn LibraryStore) -> Result<(), Errors> {
+    if let Some(threshold) = cfg.fines.block_threshold {
+        if balance(user_id, borrower, cfg, db).await? > threshold {
+            return Err(Errors::FinesOutstanding(borrower.to_string()));
+        }
+    }
+    Ok(())
+}
+
+/// Lihat sisa denda dan riwayatnya
+pub async fn get_fines(path: web::Path<UserBorrower>, query: web::Query<LedgerQuery>, cfg: Data<Config>, db: Data::<dyn LibraryStore>) -> Result<HttpResponse, Errors> {
+    // Cek kalo elastic hidup dan usernya ada
+    check_server(db.get_ref()).await?;
+    get_user_genre_list(&path.user_id, &cfg, db.get_ref()).await?;
+
+    let entries = ledger(&path.user_id, &path.borrower, &cfg, db.get_ref()).await?;
+    let (charges, payments, waivers) = totals(&entries);
+
+    // Paginasi riwayatnya
+    let from = query.from.unwrap_or(0).max(0);
+    let count = query.count.unwrap_or(cfg.library.default_page_size).max(0);
+    let data: Vec<Value> = entries.iter().skip(from as usize).take(count as usize).map(|(id, e)| {
+        let mut entry = e.clone();
+        entry["entry_id"] = json!(id);
+        entry
+    }).collect();
+    Ok(HttpResponse::Ok().json(json!({
+        "borrower": path.borrower,
+        "balance": charges - payments - waivers,
+        "charges": charges,
+        "payments": payments,
+        "waivers": waivers,
+        "blocked": cfg.fines.block_threshold.map(|t| charges - payments - waivers > t).unwrap_or(false),
+        "data": data,
+        "total": entries.len(),
+        "from": from,
+        "count": count
+    })))
+}
+
+/// Catat pembayaran atau penghapusan denda
+async fn record(path: &UserBorrower, data: &LedgerInput, kind: LedgerKind, cfg: &Config, db: &dyn LibraryStore) -> Result<HttpResponse, Errors> {
+    // Cek kalo elastic hidup dan usernya ada
+    check_server(db).await?;
+    get_user_genre_list(&path.user_id, cfg, db).await?;
+
+    // Ga boleh lebih dari sisa dendanya
+    if data.amount <= 0 {
+        return Err(Errors::Validation("amount must be more than 0".to_string()));
+    }
+    let remaining = balance(&path.user_id, &path.borrower, cfg, db).await?;
+    if data.amount > remaining {
+        return Err(Errors::Validation(format!("amount cannot be more than the balance of {}", remaining)));
+    }
+
+    create_fine_index(cfg, db).await?;
+    let now = Utc::now();
+    let entry = json!({
+        "library": path.user_id,
+        "borrower": path.borrower,
+        "kind": kind,
+        "amount": data.amount,
+        "note": data.note,
+        "created_at": timestamp(now)
+    });
+    let entry_id = format!("{}:{}:{}:{:016x}", path.user_id, path.borrower, now.timestamp_millis(), rand::random::<u64>());
+    let response = db.create_document(&cfg.library.fine_index, &entry_id, &entry).await?;
+    if !response.status_code().is_success() {
+        return Err(store_error(&response, Errors::Unknown));
+    }
+    Ok(HttpResponse::Created().json(json!({
+        "entry_id": entry_id,
+        "entry": entry,
+        "balance": remaining - data.amount
+    })))
+}
+
+/// Peminjam bayar denda
+pub async fn pay_fine(path: web::Path<UserBorrower>, data: web::Json<LedgerInput>, cfg: Data<Config>, db: Data::<dyn LibraryStore>) -> Result<HttpResponse, Errors> {
+    record(&path, &data, LedgerKind::Payment, &cfg, db.get_ref()).await
+}
+
+/// Petugas menghapuskan denda
+pub async fn waive_fine(path: web::Path<UserBorrower>, data: web::Json<LedgerInput>, cfg: Data<Config>, db: Data::<dyn LibraryStore>) -> Result<HttpResponse, Errors> {
+    record(&path, &data, LedgerKind::Waiver, &cfg, db.get_ref()).await
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, TimeZone};
+    use crate::config::FineRule;
+    use super::*;
+
+    fn due() -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 1, 31, 8, 0, 0).unwrap()
+    }
+
+    fn config(grace_days: i64, max_per_item: Option<i64>) -> Config {
+        let mut cfg = Config::default();
+        cfg.fines.daily_rate = 1000;
+        cfg.fines.grace_days = grace_days;
+        cfg.fines.max_per_item = max_per_item;
+        cfg
+    }
+
+    #[test]
+    fn returned_on_time_is_free() {
+        let cfg = config(0, None);
+        assert_eq!(late_fine("novel", due(), due(), &cfg), (0, 0));
+        assert_eq!(late_fine("novel", due(), due() - Duration::days(3), &cfg), (0, 0));
+    }
+
+    #[test]
+    fn one_second_late_counts_as_a_day() {
+        let cfg = config(0, None);
+        assert_eq!(late_fine("novel", due(), due() + Duration::seconds(1), &cfg), (1, 1000));
+        assert_eq!(late_fine("novel", due(), due() + Duration::days(1), &cfg), (1, 1000));
+        assert_eq!(late_fine("novel", due(), due() + Duration::days(1) + Duration::seconds(1), &cfg), (2, 2000));
+    }
+
+    #[test]
+    fn grace_days_are_not_charged() {
+        let cfg = config(2, None);
+        assert_eq!(late_fine("novel", due(), due() + Duration::hours(30), &cfg), (2, 0));
+        assert_eq!(late_fine("novel", due(), due() + Duration::days(5), &cfg), (5, 3000));
+    }
+
+    #[test]
+    fn amount_stops_at_the_cap() {
+        let cfg = config(0, Some(4500));
+        assert_eq!(late_fine("novel", due(), due() + Duration::days(4), &cfg), (4, 4000));
+        assert_eq!(late_fine("novel", due(), due() + Duration::days(30), &cfg), (30, 4500));
+    }
+
+    #[test]
+    fn genre_rule_overrides_the_default() {
+        let mut cfg = config(1, Some(10_000));
+        cfg.fines.genres.insert("referensi".to_string(), FineRule { daily_rate: Some(5000), grace_days: Some(0), max_per_item: Some(20_000) });
+        cfg.fines.genres.insert("komik".to_string(), FineRule { daily_rate: Some(500), ..Default::default() });
+        assert_eq!(late_fine("referensi", due(), due() + Duration::days(3), &cfg), (3, 15_000));
+        assert_eq!(late_fine("referensi", due(), due() + Duration::days(30), &cfg), (30, 20_000));
+        // Yang ga diatur di genre ikut aturan umum
+        assert_eq!(late_fine("komik", due(), due() + Duration::days(3), &cfg), (3, 1000));
+        assert_eq!(late_fine("komik", due(), due() + Duration::days(100), &cfg), (100, 10_000));
+        assert_eq!(late_fine("novel", due(), due() + Duration::days(3), &cfg), (3, 2000));
+    }
+}
@@ -1,105 +1,71 @@
-use actix_web::{web::{self, Data}, HttpResponse, http::StatusCode};
+use actix_web::{web::{self, Data}, HttpResponse};
 use serde_json::json;
-use crate::{database::Database, USER_LIST, libs::*};
+use crate::{config::Config, mapping::book_index, store::LibraryStore, libs::*};
 use super::structs::*;
 
 // Ambil genre
-pub async fn get_genre(path: web::Path<UserID>, query: web::Query<OptionalGenre>, db: Data::<Database>) -> HttpResponse {  
+pub async fn get_genre(path: web::Path<UserID>, query: web::Query<OptionalGenre>, cfg: Data<Config>, db: Data::<dyn LibraryStore>) -> Result<HttpResponse, Errors> {
 
     // Cek genre ada diisi
     match &query.genre {
         // Kalau ada, cek kalo user sama genre ada
-        Some(x) => {
-            match check_userid_genre(&path.user_id, &x.to_lowercase(), &db).await{
-                Ok(_) => (),
-                Err((s, e)) => return HttpResponse::build(s).json(json!({"error": e.to_string()}))
-            };
-        },
+        Some(x) => check_userid_genre(&path.user_id, &x.to_lowercase(), &cfg, db.get_ref()).await?,
 
         // Cek kalo elastic hidup
-        None => if !check_server(&db).await { return HttpResponse::ServiceUnavailable().json(json!({"error": Errors::ServerDown.to_string()})) }
+        None => check_server(db.get_ref()).await?
     }
 
-
     // Minta data genre ke elastic
-    let response = db.get_indices(
-        Some(
-            format!("{}.{}", &path.user_id.to_lowercase(), 
-            &query.genre.as_ref().unwrap_or(&"*".to_owned()).to_lowercase())
-        )).await.unwrap();
+    let genre = query.genre.as_ref().unwrap_or(&"*".to_owned()).to_lowercase();
+    let response = db.get_indices(Some(book_index(&path.user_id, &genre))).await?;
 
     if !response.status_code().is_success(){
-        return match response.status_code() {
-            StatusCode::NOT_FOUND => HttpResponse::NotFound().finish(),
-            _ => HttpResponse::build(response.status_code()).json(json!({"error": Errors::Unknown.to_string()}))
-        }
+        return Err(store_error(&response, Errors::GenreNotFound(genre)));
     }
-    HttpResponse::build(response.status_code()).json(response.json::<Vec<IndexResponse>>().await.unwrap())
+    Ok(HttpResponse::build(response.status_code()).json(serde_json::from_value::<Vec<IndexResponse>>(response.body)?))
 }
 
 // Buat genre baru
-pub async fn create_genre(path: web::Path<UserID>, data: web::Json<Genre>, db: Data::<Database>) -> HttpResponse {  
+pub async fn create_genre(path: web::Path<UserID>, data: web::Json<Genre>, cfg: Data<Config>, db: Data::<dyn LibraryStore>) -> Result<HttpResponse, Errors> {
 
     // Cek kalo elastic hidup
-    if !check_server(&db).await { return HttpResponse::ServiceUnavailable().json(json!({"error": Errors::ServerDown.to_string()})); };
-    
+    check_server(db.get_ref()).await?;
+
     // Konversi genre supaya stringnya valid pas lempar ke elastic
     let genre: String = data.genre.to_lowercase().chars().map(|c| if !c.is_ascii() || c.is_whitespace() {'_'} else {c}).collect();
 
-    // Cek kalo genre udah ada
-    match genre_exists(&path.user_id, &genre, &db).await {
-        
-        // Kalo ada, gagalin
-        Ok(_) => HttpResponse::Conflict().json(json!({"error": Errors::GenreExists(genre).to_string()})),
-
-        // Kalo eror, berarti antara belum ada atau usernya engga ada
-        Err((s, e, mut l)) => match e {
-
-            // User engga ada, langsung gagalin karena gabisa dibuat
-            Errors::UserNotFound(_) => HttpResponse::build(s).json(json!({"error": e.to_string()})),
-
-            // Genre engga ada, berarti bisa buat
-            Errors::GenreNotFound(_) => {
-                l.insert(genre.clone());
-                let body = json!({"genres": l});
-                let _ = db.update_single_document(USER_LIST, &path.user_id, &body).await;
-                create_new_genre(Some(path.user_id.to_string()), &genre, &db).await;
-                HttpResponse::Created().finish()
-            },
+    // Cek kalo genre udah ada, kalo usernya engga ada langsung gagal karena gabisa dibuat
+    let mut l = get_user_genre_list(&path.user_id, &cfg, db.get_ref()).await?;
+    if l.contains(&genre) {
+        return Err(Errors::GenreExists(genre));
+    }
 
-            // Gatau eror apa
-            _ => HttpResponse::build(s).json(json!({"error": Errors::Unknown.to_string()}))
-        }
+    // Genre engga ada, berarti bisa buat
+    l.insert(genre.clone());
+    let response = db.update_single_document(&cfg.library.user_index, &path.user_id, &json!({"genres": l})).await?;
+    if !response.status_code().is_success() {
+        return Err(store_error(&response, Errors::UserNotFound(path.user_id.to_string())));
     }
+    create_new_genre(Some(path.user_id.to_string()), &genre, &cfg, db.get_ref()).await?;
+    Ok(HttpResponse::Created().finish())
 }
 
 // Hapus genre
-pub async fn delete_genre(path: web::Path<UserGenre>, db: Data::<Database>) -> HttpResponse {  
+pub async fn delete_genre(path: web::Path<UserGenre>, cfg: Data<Config>, db: Data::<dyn LibraryStore>) -> Result<HttpResponse, Errors> {
 
     // Cek kalo user sama genre ada
     let genre = path.genre.to_lowercase();
-    match check_userid_genre(&path.user_id, &genre, &db).await{
-        Ok(_) => (),
-        Err((s, e)) => return HttpResponse::build(s).json(json!({"error": e.to_string()}))
-    };
+    check_userid_genre(&path.user_id, &genre, &cfg, db.get_ref()).await?;
 
     // Langsung kirim minta hapus
-    let code = db.delete_single_index(format!("{}.{}", &path.user_id.to_lowercase(), &genre)).await.unwrap().status_code();
-
-    if !code.is_success(){
-        return match code {
-            StatusCode::NOT_FOUND => HttpResponse::NotFound().finish(),
-            _ => HttpResponse::build(code).json(json!({"error": Errors::Unknown.to_string()}))
-        }
+    let response = db.delete_single_index(book_index(&path.user_id, &genre)).await?;
+    if !response.status_code().is_success(){
+        return Err(store_error(&response, Errors::GenreNotFound(genre)));
     }
 
     // Kalo berhasil dihapus, hapus juga yang ada di data usernya
-    match genre_exists(&path.user_id, &genre, &db).await {
-        Ok(mut l) => {
-            l.remove(&genre);
-            let _ = db.update_single_document(USER_LIST, &path.user_id, &json!({"genres": l})).await;
-            HttpResponse::build(code).finish()
-        },
-        Err((s, e, _)) => HttpResponse::build(s).json(json!({"error": e.to_string()})),
-    }
-}
\ No newline at end of file
+    let mut l = genre_exists(&path.user_id, &genre, &cfg, db.get_ref()).await?;
+    l.remove(&genre);
+    db.update_single_document(&cfg.library.user_index, &path.user_id, &json!({"genres": l})).await?;
+    Ok(HttpResponse::build(response.status_code()).finish())
+}
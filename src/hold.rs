@@ -0,0 +1,298 @@
+use actix_web::{http::StatusCode, web::{self, Data}, HttpResponse};
+use chrono::{Duration, SecondsFormat, Utc};
+use serde_json::{json, Value};
+use crate::{auth::Caller, config::Config, copy::*, loan::is_on_loan, mapping::book_index, store::LibraryStore, libs::*};
+use super::structs::*;
+
+/// Id pesanan yang masih di antrean, satu pemesan cuma bisa antre sekali untuk satu buku
+fn active_hold_id(user_id: &str, genre: &str, book_id: &str, patron: &str) -> String {
+    format!("{}:{}:{}:{}", user_id, genre, book_id, patron)
+}
+
+/// Waktu pesan sampai milidetik supaya urutan antreannya jelas
+fn queue_timestamp() -> String {
+    Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true)
+}
+
+/// Member cuma boleh antre atas nama sendiri, petugas boleh atas nama siapa aja
+fn patron_for(caller: &Caller, requested: Option<&str>) -> Result<String, Errors> {
+    let patron = match requested.map(str::trim).filter(|p| !p.is_empty()) {
+        Some(p) => p.to_string(),
+        None => caller.user_id.clone().ok_or(Errors::Validation("patron is required".to_string()))?
+    };
+    caller.require_patron(&patron)?;
+    Ok(patron)
+}
+
+/// Indeks pesanan harus selalu ada
+async fn create_hold_index(cfg: &Config, db: &dyn LibraryStore) -> Result<(), Errors> {
+    let body =
+        json!(
+            {
+                "mappings": {
+                    "properties": {
+                        "library": {"type": "keyword"},
+                        "genre": {"type": "keyword"},
+                        "book_id": {"type": "keyword"},
+                        "patron": {"type": "keyword"},
+                        "status": {"type": "keyword"},
+                        "barcode": {"type": "keyword"},
+                        "placed_at": {"type": "date"},
+                        "ready_at": {"type": "date"},
+                        "pickup_by": {"type": "date"},
+                        "ended_at": {"type": "date"}
+                    }
+                }
+            }
+        );
+    create_index_if_missing(&cfg.library.hold_index, &body, db).await
+}
+
+/// Cari pesanan urut dari yang paling dulu antre, hasilnya (id, isi)
+async fn search_holds(filter: Vec<Value>, must_not: Vec<Value>, from: Option<i64>, count: Option<i64>, cfg: &Config, db: &dyn LibraryStore) -> Result<(Vec<(String, Value)>, i64), Errors> {
+    let body = json!({
+        "query": {
+            "bool": {
+                "filter": filter,
+                "must_not": must_not
+            }
+        },
+        "sort": [{"placed_at": "asc"}]
+    });
+    let response = db.search(&cfg.library.hold_index, &body, from, Some(count.unwrap_or(10_000))).await?;
+    match response.status_code() {
+        s if s.is_success() => (),
+        StatusCode::NOT_FOUND => return Ok((vec![], 0)),
+        _ => return Err(store_error(&response, Errors::Unknown))
+    }
+    let holds = response.body["hits"]["hits"].as_array().map(|hits| hits.iter().map(|h| {
+        (h["_id"].as_str().unwrap_or_default().to_string(), h["_source"].clone())
+    }).collect()).unwrap_or_default();
+    Ok((holds, response.body["hits"]["total"]["value"].as_i64().unwrap_or_default()))
+}
+
+/// Filter untuk semua pesanan satu buku dengan status tertentu
+fn book_filter(user_id: &str, genre: &str, book_id: &str, status: HoldStatus) -> Vec<Value> {
+    vec![
+        json!({"term": {"library": user_id}}),
+        json!({"term": {"genre": genre}}),
+        json!({"term": {"book_id": book_id}}),
+        json!({"term": {"status": status}})
+    ]
+}
+
+/// Akhiri pesanan, simpan ke riwayat dulu baru hapus yang aktif
+async fn end_hold(hold_id: &str, mut hold: Value, status: HoldStatus, cfg: &Config, db: &dyn LibraryStore) -> Result<Value, Errors> {
+    hold["status"] = json!(status);
+    hold["ended_at"] = json!(timestamp(Utc::now()));
+    let history_id = format!("{}:{}", hold_id, hold["placed_at"].as_str().unwrap_or_default());
+    let response = db.create_document(&cfg.library.hold_index, &history_id, &hold).await?;
+    if !response.status_code().is_success() && response.status_code() != StatusCode::CONFLICT {
+        return Err(store_error(&response, Errors::Unknown));
+    }
+    let response = db.delete_single_document(&cfg.library.hold_index, hold_id).await?;
+    if !response.status_code().is_success() && response.status_code() != StatusCode::NOT_FOUND {
+        return Err(store_error(&response, Errors::Unknown));
+    }
+    Ok(hold)
+}
+
+/// Buku atau eksemplar baru lepas, kasih ke pemesan berikutnya. Kalo ga ada yang antre eksemplarnya tersedia lagi
+pub async fn promote_next(user_id: &str, genre: &str, book_id: &str, barcode: Option<&str>, cfg: &Config, db: &dyn LibraryStore) -> Result<(), Errors> {
+    let (waiting, _) = search_holds(book_filter(user_id, genre, book_id, HoldStatus::Waiting), vec![], None, Some(1), cfg, db).await?;
+    match waiting.first() {
+        Some((hold_id, _)) => {
+            let now = Utc::now();
+            let mut changes = json!({
+                "status": HoldStatus::Ready,
+                "ready_at": timestamp(now),
+                "pickup_by": timestamp(now + Duration::days(cfg.library.hold_pickup_days))
+            });
+            if let Some(b) = barcode {
+                changes["barcode"] = json!(b);
+            }
+            let response = db.update_single_document(&cfg.library.hold_index, hold_id, &changes).await?;
+            if !response.status_code().is_success() {
+                return Err(store_error(&response, Errors::Unknown));
+            }
+            if let Some(b) = barcode {
+                set_copy_status(user_id, b, CopyStatus::OnHold, cfg, db).await?;
+            }
+        },
+        None => if let Some(b) = barcode {
+            set_copy_status(user_id, b, CopyStatus::Available, cfg, db).await?;
+        }
+    }
+    Ok(())
+}
+
+/// Pesanan siap ambil yang lewat batas dianggap hangus, bukunya dikasih ke antrean berikutnya.
+/// Dicek tiap kali buku atau antrean disentuh, book kosong berarti semua buku di library
+pub async fn expire_holds(user_id: &str, book: Option<(&str, &str)>, cfg: &Config, db: &dyn LibraryStore) -> Result<(), Errors> {
+    let mut filter = match book {
+        Some((genre, book_id)) => book_filter(user_id, genre, book_id, HoldStatus::Ready),
+        None => vec![json!({"term": {"library": user_id}}), json!({"term": {"status": HoldStatus::Ready}})]
+    };
+    filter.push(json!({"range": {"pickup_by": {"lt": timestamp(Utc::now())}}}));
+    let (expired, _) = search_holds(filter, vec![], None, None, cfg, db).await?;
+    for (hold_id, hold) in expired {
+        let hold = end_hold(&hold_id, hold, HoldStatus::Expired, cfg, db).await?;
+        let (genre, book_id) = (hold["genre"].as_str().unwrap_or_default(), hold["book_id"].as_str().unwrap_or_default());
+        promote_next(user_id, genre, book_id, hold["barcode"].as_str(), cfg, db).await?;
+    }
+    Ok(())
+}
+
+/// Pesanan yang sudah siap ambil untuk buku atau eksemplar ini, hasilnya (id, isi)
+pub async fn ready_hold(user_id: &str, genre: &str, book_id: &str, barcode: Option<&str>, cfg: &Config, db: &dyn LibraryStore) -> Result<Option<(String, Value)>, Errors> {
+    let mut filter = book_filter(user_id, genre, book_id, HoldStatus::Ready);
+    let mut must_not = vec![];
+    match barcode {
+        Some(b) => filter.push(json!({"term": {"barcode": b}})),
+        None => must_not.push(json!({"exists": {"field": "barcode"}}))
+    }
+    let (holds, _) = search_holds(filter, must_not, None, Some(1), cfg, db).await?;
+    Ok(holds.into_iter().next())
+}
+
+/// Cek kalo ada orang lain yang antre untuk buku ini
+pub async fn has_waiting_holds(user_id: &str, genre: &str, book_id: &str, except_patron: &str, cfg: &Config, db: &dyn LibraryStore) -> Result<bool, Errors> {
+    let must_not = vec![json!({"term": {"patron": except_patron}})];
+    let (_, total) = search_holds(book_filter(user_id, genre, book_id, HoldStatus::Waiting), must_not, None, Some(0), cfg, db).await?;
+    Ok(total > 0)
+}
+
+/// Pemesan sudah ambil bukunya
+pub async fn fulfil_hold(hold_id: &str, hold: Value, cfg: &Config, db: &dyn LibraryStore) -> Result<(), Errors> {
+    end_hold(hold_id, hold, HoldStatus::Fulfilled, cfg, db).await?;
+    Ok(())
+}
+
+/// Bentuk pesanan yang dikirim ke client
+fn hold_view(hold_id: &str, hold: &Value, position: Option<usize>) -> Value {
+    json!({
+        "hold_id": hold_id,
+        "library": hold["library"],
+        "genre": hold["genre"],
+        "book_id": hold["book_id"],
+        "patron": hold["patron"],
+        "status": hold["status"],
+        "position": position,
+        "barcode": hold["barcode"],
+        "placed_at": hold["placed_at"],
+        "ready_at": hold["ready_at"],
+        "pickup_by": hold["pickup_by"],
+        "ended_at": hold["ended_at"]
+    })
+}
+
+/// Antrean satu buku, yang siap ambil dulu lalu yang menunggu sesuai urutan
+async fn book_queue(user_id: &str, genre: &str, book_id: &str, cfg: &Config, db: &dyn LibraryStore) -> Result<Vec<Value>, Errors> {
+    let (ready, _) = search_holds(book_filter(user_id, genre, book_id, HoldStatus::Ready), vec![], None, None, cfg, db).await?;
+    let (waiting, _) = search_holds(book_filter(user_id, genre, book_id, HoldStatus::Waiting), vec![], None, None, cfg, db).await?;
+    let mut queue: Vec<Value> = ready.iter().map(|(id, h)| hold_view(id, h, None)).collect();
+    queue.extend(waiting.iter().enumerate().map(|(i, (id, h))| hold_view(id, h, Some(i + 1))));
+    Ok(queue)
+}
+
+/// Pesan buku yang semua eksemplarnya sedang keluar
+pub async fn place_hold(path: web::Path<UserBookID>, data: web::Json<HoldInput>, caller: Caller, cfg: Data<Config>, db: Data::<dyn LibraryStore>) -> Result<HttpResponse, Errors> {
+    let patron = patron_for(&caller, data.patron.as_deref())?;
+
+    // Cek kalo user, genre dan bukunya ada
+    let genre = path.genre.to_lowercase();
+    check_userid_genre(&path.user_id, &genre, &cfg, db.get_ref()).await?;
+    get_book(&book_index(&path.user_id, &genre), &path.book_id, Some("judul".to_string()), db.get_ref()).await?;
+    create_hold_index(&cfg, db.get_ref()).await?;
+    expire_holds(&path.user_id, Some((&genre, &path.book_id)), &cfg, db.get_ref()).await?;
+
+    // Kalo masih ada yang bisa dipinjam ga perlu antre
+    let available = match count_copies(&path.user_id, &genre, &path.book_id, &cfg, db.get_ref()).await? {
+        0 => !is_on_loan(&path.user_id, &genre, &path.book_id, &cfg, db.get_ref()).await?
+            && ready_hold(&path.user_id, &genre, &path.book_id, None, &cfg, db.get_ref()).await?.is_none(),
+        _ => {
+            let book = (genre.clone(), path.book_id.to_string());
+            available_copies(&path.user_id, std::slice::from_ref(&book), &cfg, db.get_ref()).await?.get(&book).copied().unwrap_or(0) > 0
+        }
+    };
+    if available {
+        return Err(Errors::BookAvailable(path.book_id.to_string()));
+    }
+
+    // Simpan dengan id tetap, kalo pemesannya sudah antre elastic nolak dengan 409
+    let hold_id = active_hold_id(&path.user_id, &genre, &path.book_id, &patron);
+    let hold = json!({
+        "library": path.user_id,
+        "genre": genre,
+        "book_id": path.book_id,
+        "patron": patron,
+        "status": HoldStatus::Waiting,
+        "placed_at": queue_timestamp()
+    });
+    let response = db.create_document(&cfg.library.hold_index, &hold_id, &hold).await?;
+    match response.status_code() {
+        s if s.is_success() => (),
+        StatusCode::CONFLICT => return Err(Errors::HoldExists(path.book_id.to_string())),
+        _ => return Err(store_error(&response, Errors::Unknown))
+    }
+
+    // Kasih tahu urutannya di antrean
+    let queue = book_queue(&path.user_id, &genre, &path.book_id, &cfg, db.get_ref()).await?;
+    let position = queue.iter().find(|h| h["hold_id"] == hold_id.as_str()).and_then(|h| h["position"].as_u64()).map(|p| p as usize);
+    Ok(HttpResponse::Created().json(hold_view(&hold_id, &hold, position)))
+}
+
+/// Batalkan pesanan, kalo bukunya sudah disiapkan langsung dikasih ke antrean berikutnya
+pub async fn cancel_hold(path: web::Path<UserBookID>, query: web::Query<HoldInput>, caller: Caller, cfg: Data<Config>, db: Data::<dyn LibraryStore>) -> Result<HttpResponse, Errors> {
+    let patron = patron_for(&caller, query.patron.as_deref())?;
+
+    // Cek kalo elastic hidup
+    check_server(db.get_ref()).await?;
+
+    let genre = path.genre.to_lowercase();
+    let hold_id = active_hold_id(&path.user_id, &genre, &path.book_id, &patron);
+    let response = db.get_single_document(&cfg.library.hold_index, &hold_id, None).await?;
+    if !response.status_code().is_success() {
+        return Err(store_error(&response, Errors::HoldNotFound(patron)));
+    }
+
+    let was_ready = response.body["status"] == json!(HoldStatus::Ready);
+    let hold = end_hold(&hold_id, response.body, HoldStatus::Cancelled, &cfg, db.get_ref()).await?;
+    if was_ready {
+        promote_next(&path.user_id, &genre, &path.book_id, hold["barcode"].as_str(), &cfg, db.get_ref()).await?;
+    }
+    Ok(HttpResponse::Ok().json(hold_view(&hold_id, &hold, None)))
+}
+
+/// Lihat antrean satu buku
+pub async fn get_book_holds(path: web::Path<UserBookID>, cfg: Data<Config>, db: Data::<dyn LibraryStore>) -> Result<HttpResponse, Errors> {
+    let genre = path.genre.to_lowercase();
+    check_userid_genre(&path.user_id, &genre, &cfg, db.get_ref()).await?;
+    expire_holds(&path.user_id, Some((&genre, &path.book_id)), &cfg, db.get_ref()).await?;
+    Ok(HttpResponse::Ok().json(book_queue(&path.user_id, &genre, &path.book_id, &cfg, db.get_ref()).await?))
+}
+
+/// Lihat semua pesanan satu library, misal yang siap ambil untuk disiapkan di meja petugas
+pub async fn get_holds(path: web::Path<UserID>, query: web::Query<HoldQuery>, cfg: Data<Config>, db: Data::<dyn LibraryStore>) -> Result<HttpResponse, Errors> {
+    // Cek kalo elastic hidup dan usernya ada
+    check_server(db.get_ref()).await?;
+    get_user_genre_list(&path.user_id, &cfg, db.get_ref()).await?;
+    expire_holds(&path.user_id, None, &cfg, db.get_ref()).await?;
+
+    // Tanpa status yang diminta, tampilkan yang masih di antrean
+    let mut filter = vec![json!({"term": {"library": path.user_id}})];
+    match query.status {
+        Some(status) => filter.push(json!({"term": {"status": status}})),
+        None => filter.push(json!({"terms": {"status": [HoldStatus::Waiting, HoldStatus::Ready]}}))
+    }
+    let count = query.count.unwrap_or(cfg.library.default_page_size);
+    let (holds, total) = search_holds(filter, vec![], query.from, Some(count), &cfg, db.get_ref()).await?;
+
+    let data: Vec<Value> = holds.iter().map(|(id, h)| hold_view(id, h, None)).collect();
+    Ok(HttpResponse::Ok().json(json!({
+        "data": data,
+        "total": total,
+        "from": &query.from.unwrap_or(0),
+        "count": &count
+    })))
+}
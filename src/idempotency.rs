@@ -0,0 +1,129 @@
+use std::future::{ready, Future, Ready};
+use actix_web::{dev::Payload, http::StatusCode, FromRequest, HttpRequest, HttpResponse};
+use chrono::{DateTime, Duration, Utc};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use crate::{config::Config, store::LibraryStore, libs::*};
+
+/// Isi header Idempotency-Key kalo dikirim
+pub struct IdempotencyKey(pub Option<String>);
+
+impl FromRequest for IdempotencyKey {
+    type Error = Errors;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let key = match req.headers().get("Idempotency-Key") {
+            None => Ok(None),
+            Some(v) => match v.to_str().map(str::trim) {
+                Ok(k) if !k.is_empty() && k.len() <= 255 => Ok(Some(k.to_string())),
+                _ => Err(Errors::Validation("Idempotency-Key must be 1 to 255 visible characters".to_string()))
+            }
+        };
+        ready(key.map(IdempotencyKey))
+    }
+}
+
+/// Sidik permintaan, kunci yang sama harus dipakai untuk isi permintaan yang sama
+pub fn fingerprint(parts: &[&[u8]]) -> String {
+    let mut hasher = Sha256::new();
+    for part in parts {
+        hasher.update((part.len() as u64).to_be_bytes());
+        hasher.update(part);
+    }
+    hasher.finalize().iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Indeks respons harus selalu ada, isi responsnya ga perlu dipetakan
+async fn create_idempotency_index(cfg: &Config, db: &dyn LibraryStore) -> Result<(), Errors> {
+    let body = json!({
+        "mappings": {
+            "properties": {
+                "fingerprint": {"type": "keyword"},
+                "state": {"type": "keyword"},
+                "status": {"type": "integer"},
+                "response": {"type": "object", "enabled": false},
+                "created_at": {"type": "date"}
+            }
+        }
+    });
+    create_index_if_missing(&cfg.library.idempotency_index, &body, db).await
+}
+
+/// Kunci dipasang dulu sebelum dikerjakan, kalo sudah ada hasilnya respons lama yang dikirim
+async fn begin(id: &str, key: &str, print: &str, cfg: &Config, db: &dyn LibraryStore) -> Result<Option<(StatusCode, Value)>, Errors> {
+    create_idempotency_index(cfg, db).await?;
+    let pending = json!({"fingerprint": print, "state": "pending", "created_at": timestamp(Utc::now())});
+
+    // Dua kali coba, sekali lagi kalo kunci lamanya sudah kedaluwarsa
+    for _ in 0..2 {
+        let response = db.create_document(&cfg.library.idempotency_index, id, &pending).await?;
+        if response.status_code().is_success() {
+            return Ok(None);
+        }
+        if response.status_code() != StatusCode::CONFLICT {
+            return Err(store_error(&response, Errors::Unknown));
+        }
+
+        let response = db.get_single_document(&cfg.library.idempotency_index, id, None).await?;
+        if response.status_code() == StatusCode::NOT_FOUND {
+            continue;
+        }
+        if !response.status_code().is_success() {
+            return Err(store_error(&response, Errors::Unknown));
+        }
+        let saved = response.body;
+
+        // Kunci yang sudah lewat masa simpannya dibuang lalu dipasang lagi
+        let expired = saved["created_at"].as_str()
+            .and_then(|d| DateTime::parse_from_rfc3339(d).ok())
+            .map(|d| d.with_timezone(&Utc) + Duration::hours(cfg.library.idempotency_hours) < Utc::now())
+            .unwrap_or(true);
+        if expired {
+            db.delete_single_document(&cfg.library.idempotency_index, id).await?;
+            continue;
+        }
+
+        if saved["fingerprint"].as_str() != Some(print) {
+            return Err(Errors::IdempotencyMismatch(key.to_string()));
+        }
+        if saved["state"] != "done" {
+            return Err(Errors::IdempotencyInProgress(key.to_string()));
+        }
+        let status = saved["status"].as_u64().and_then(|s| StatusCode::from_u16(s as u16).ok()).unwrap_or(StatusCode::OK);
+        return Ok(Some((status, saved["response"].clone())));
+    }
+    Err(Errors::IdempotencyInProgress(key.to_string()))
+}
+
+/// Jalankan permintaan sekali aja per Idempotency-Key, permintaan ulang dapat respons yang sama.
+/// Kalo ga ada kuncinya langsung dijalankan
+pub async fn run_once<F>(key: &IdempotencyKey, user_id: &str, print: &str, cfg: &Config, db: &dyn LibraryStore, work: F) -> Result<HttpResponse, Errors>
+where
+    F: Future<Output = Result<Value, Errors>>
+{
+    let key = match &key.0 {
+        Some(k) => k,
+        None => return Ok(HttpResponse::Ok().json(work.await?))
+    };
+
+    let id = format!("{}:{}", user_id, key);
+    if let Some((status, body)) = begin(&id, key, print, cfg, db).await? {
+        return Ok(HttpResponse::build(status).insert_header(("Idempotent-Replayed", "true")).json(body));
+    }
+
+    // Kalo gagal kuncinya dilepas supaya bisa dicoba lagi
+    let body = match work.await {
+        Ok(b) => b,
+        Err(e) => {
+            db.delete_single_document(&cfg.library.idempotency_index, &id).await?;
+            return Err(e);
+        }
+    };
+    let done = json!({"state": "done", "status": StatusCode::OK.as_u16(), "response": body});
+    let response = db.update_single_document(&cfg.library.idempotency_index, &id, &done).await?;
+    if !response.status_code().is_success() {
+        return Err(store_error(&response, Errors::Unknown));
+    }
+    Ok(HttpResponse::Ok().json(body))
+}
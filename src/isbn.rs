@@ -0,0 +1,163 @@
+use serde_json::{json, Value};
+
+/// Ambil digitnya aja, tanda hubung dan spasi dibuang. Huruf X cuma boleh di akhir ISBN-10
+fn digits(raw: &str) -> Result<Vec<u32>, String> {
+    let cleaned: Vec<char> = raw.chars().filter(|c| !matches!(c, '-' | ' ' | '\u{2010}'..='\u{2015}')).collect();
+    let mut out = Vec::with_capacity(cleaned.len());
+    for (i, c) in cleaned.iter().enumerate() {
+        match c {
+            '0'..='9' => out.push(c.to_digit(10).unwrap_or_default()),
+            'X' | 'x' if cleaned.len() == 10 && i == 9 => out.push(10),
+            _ => return Err(format!("invalid character '{}'", c))
+        }
+    }
+    Ok(out)
+}
+
+/// Check digit ISBN-10, 10 artinya X
+fn isbn10_check(first: &[u32]) -> u32 {
+    let sum: u32 = first.iter().enumerate().map(|(i, d)| d * (10 - i as u32)).sum();
+    (11 - sum % 11) % 11
+}
+
+/// Check digit ISBN-13
+fn isbn13_check(first: &[u32]) -> u32 {
+    let sum: u32 = first.iter().enumerate().map(|(i, d)| if i % 2 == 0 { *d } else { d * 3 }).sum();
+    (10 - sum % 10) % 10
+}
+
+fn to_string(digits: &[u32]) -> String {
+    digits.iter().map(|d| if *d == 10 { 'X' } else { char::from_digit(*d, 10).unwrap_or('0') }).collect()
+}
+
+/// Cek check digit lalu jadikan ISBN-13 tanpa tanda hubung
+pub fn normalize(raw: &str) -> Result<String, String> {
+    let d = digits(raw.trim())?;
+    match d.len() {
+        10 => {
+            if isbn10_check(&d[..9]) != d[9] {
+                return Err("wrong ISBN-10 check digit".to_string());
+            }
+            let mut isbn13 = vec![9, 7, 8];
+            isbn13.extend_from_slice(&d[..9]);
+            isbn13.push(isbn13_check(&isbn13));
+            Ok(to_string(&isbn13))
+        },
+        13 => {
+            if d[..3] != [9, 7, 8] && d[..3] != [9, 7, 9] {
+                return Err("ISBN-13 must start with 978 or 979".to_string());
+            }
+            if isbn13_check(&d[..12]) != d[12] {
+                return Err("wrong ISBN-13 check digit".to_string());
+            }
+            Ok(to_string(&d))
+        },
+        n => Err(format!("expected 10 or 13 digits, got {}", n))
+    }
+}
+
+/// ISBN-13 yang diawali 978 bisa dijadikan ISBN-10, yang 979 ga punya padanannya
+pub fn to_isbn10(isbn13: &str) -> Option<String> {
+    let d = digits(isbn13).ok()?;
+    if d.len() != 13 || d[..3] != [9, 7, 8] {
+        return None;
+    }
+    let mut isbn10 = d[3..12].to_vec();
+    isbn10.push(isbn10_check(&isbn10));
+    Some(to_string(&isbn10))
+}
+
+/// Rapikan isbn di dokumen buku: isbn jadi ISBN-13, aslinya disimpan di isbn_raw,
+/// dan isbn_10 diisi kalo ada padanannya. Kalo ga valid kirim alasannya.
+/// Isbn yang dikosongkan ikut mengosongkan isbn_10 dan isbn_raw, supaya update ga menyisakan isbn lama
+pub fn normalize_book(book: &mut Value) -> Result<(), String> {
+    let raw = match book.get("isbn") {
+        None => return Ok(()),
+        Some(Value::String(s)) if !s.trim().is_empty() => s.clone(),
+        Some(Value::Null | Value::String(_)) => {
+            clear_isbn(book);
+            return Ok(());
+        },
+        Some(_) => return Err("Invalid ISBN: must be a string".to_string())
+    };
+    let isbn13 = normalize(&raw).map_err(|e| format!("Invalid ISBN '{}': {}", raw, e))?;
+    book["isbn_10"] = json!(to_isbn10(&isbn13));
+    book["isbn"] = json!(isbn13);
+    book["isbn_raw"] = json!(raw);
+    Ok(())
+}
+
+fn clear_isbn(book: &mut Value) {
+    for field in ["isbn", "isbn_10", "isbn_raw"] {
+        book[field] = Value::Null;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn valid_isbn13_is_kept() {
+        assert_eq!(normalize("9789793062792"), Ok("9789793062792".to_string()));
+        assert_eq!(normalize("978-979-3062-79-2"), Ok("9789793062792".to_string()));
+        assert_eq!(normalize(" 979 10 90636 07 1 "), Ok("9791090636071".to_string()));
+    }
+
+    #[test]
+    fn valid_isbn10_becomes_isbn13() {
+        assert_eq!(normalize("979-3062-79-7"), Ok("9789793062792".to_string()));
+        assert_eq!(normalize("0306406152"), Ok("9780306406157".to_string()));
+    }
+
+    #[test]
+    fn check_digit_x_in_any_case() {
+        assert_eq!(normalize("080442957X"), Ok("9780804429573".to_string()));
+        assert_eq!(normalize("0-8044-2957-x"), Ok("9780804429573".to_string()));
+        assert!(normalize("X804429570").is_err());
+        assert!(normalize("978080442957X").is_err());
+    }
+
+    #[test]
+    fn invalid_isbns_are_rejected() {
+        assert_eq!(normalize("0306406153"), Err("wrong ISBN-10 check digit".to_string()));
+        assert_eq!(normalize("9780306406158"), Err("wrong ISBN-13 check digit".to_string()));
+        assert_eq!(normalize("9770306406157"), Err("ISBN-13 must start with 978 or 979".to_string()));
+        assert_eq!(normalize("12345"), Err("expected 10 or 13 digits, got 5".to_string()));
+        assert_eq!(normalize("97803064a6157"), Err("invalid character 'a'".to_string()));
+    }
+
+    #[test]
+    fn isbn10_round_trips() {
+        for isbn10 in ["0306406152", "080442957X", "9793062797"] {
+            let isbn13 = normalize(isbn10).unwrap();
+            assert_eq!(to_isbn10(&isbn13), Some(isbn10.to_string()));
+        }
+        assert_eq!(to_isbn10("9791090636071"), None);
+    }
+
+    #[test]
+    fn book_keeps_raw_and_both_forms() {
+        let mut book = serde_json::json!({"judul": "Laskar Pelangi", "isbn": "979-3062-79-7"});
+        normalize_book(&mut book).unwrap();
+        assert_eq!(book["isbn"], "9789793062792");
+        assert_eq!(book["isbn_10"], "9793062797");
+        assert_eq!(book["isbn_raw"], "979-3062-79-7");
+
+        let mut book = serde_json::json!({"isbn": "123"});
+        assert!(normalize_book(&mut book).is_err());
+    }
+
+    #[test]
+    fn emptied_isbn_clears_both_forms() {
+        for isbn in [serde_json::json!(""), serde_json::json!("  "), Value::Null] {
+            let mut book = serde_json::json!({"isbn": isbn});
+            normalize_book(&mut book).unwrap();
+            assert_eq!(book, serde_json::json!({"isbn": null, "isbn_10": null, "isbn_raw": null}));
+        }
+
+        let mut book = serde_json::json!({"judul": "Tanpa isbn"});
+        normalize_book(&mut book).unwrap();
+        assert_eq!(book, serde_json::json!({"judul": "Tanpa isbn"}));
+    }
+}
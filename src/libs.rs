@@ -1,7 +1,8 @@
 use std::collections::HashSet;
-use actix_web::http::StatusCode;
+use actix_web::{http::StatusCode, HttpResponse, ResponseError};
+use chrono::{DateTime, SecondsFormat, Utc};
 use serde_json::{json, Value};
-use crate::{database::Database, USER_LIST};
+use crate::{config::Config, mapping::{book_index, create_book_index, user_index_body}, store::{LibraryStore, StoreResponse}, structs::{FieldError, Failures}};
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -14,102 +15,252 @@ pub enum Errors {
     GenreExists(String),
     #[error("Cannot find book with ID: {0}")]
     BookNotFound(String),
+    #[error("Book is already on loan: {0}")]
+    BookOnLoan(String),
+    #[error("Book is not on loan: {0}")]
+    LoanNotFound(String),
+    #[error("Cannot find copy with barcode: {0}")]
+    CopyNotFound(String),
+    #[error("Copy with barcode already exist: {0}")]
+    CopyExists(String),
+    #[error("Copy is not available: {0}")]
+    CopyNotAvailable(String),
+    #[error("Book is waiting for the patron who placed a hold: {0}")]
+    BookOnHold(String),
+    #[error("Book is available, borrow it instead of placing a hold: {0}")]
+    BookAvailable(String),
+    #[error("Patron already has a hold on this book: {0}")]
+    HoldExists(String),
+    #[error("Cannot find hold for patron: {0}")]
+    HoldNotFound(String),
+    #[error("Borrower has unpaid fines above the limit: {0}")]
+    FinesOutstanding(String),
+    #[error("Loan cannot be renewed: {0}")]
+    RenewalRefused(String),
+    #[error("A request with this Idempotency-Key is still being processed: {0}")]
+    IdempotencyInProgress(String),
+    #[error("Idempotency-Key was already used for a different request: {0}")]
+    IdempotencyMismatch(String),
+    #[error("Search cursor has expired, start the search again")]
+    CursorExpired,
     #[error("Bad Data Given")]
     BadRequest,
+    #[error("{0}")]
+    Validation(String),
+    #[error("Invalid fields: {}", field_names(.0))]
+    InvalidFields(Vec<FieldError>),
+    #[error("{0}")]
+    Conflict(String),
+    #[error("Missing or invalid API key")]
+    Unauthorized,
+    #[error("{0}")]
+    Forbidden(String),
     #[error("Database server is offline")]
     ServerDown,
+    #[error("Database request failed: {0}")]
+    Transport(#[from] elasticsearch::Error),
+    #[error("Cannot read database response: {0}")]
+    Deserialize(#[from] serde_json::Error),
     #[error("Unknown error has occured")]
     Unknown
 }
 
+impl Errors {
+    /// Kode eror yang tetap, supaya client ga perlu baca pesan erornya
+    pub fn code(&self) -> &'static str {
+        match self {
+            Errors::UserNotFound(_) => "user_not_found",
+            Errors::GenreNotFound(_) => "genre_not_found",
+            Errors::GenreExists(_) => "genre_exists",
+            Errors::BookNotFound(_) => "book_not_found",
+            Errors::BookOnLoan(_) => "book_on_loan",
+            Errors::LoanNotFound(_) => "loan_not_found",
+            Errors::CopyNotFound(_) => "copy_not_found",
+            Errors::CopyExists(_) => "copy_exists",
+            Errors::CopyNotAvailable(_) => "copy_not_available",
+            Errors::BookOnHold(_) => "book_on_hold",
+            Errors::BookAvailable(_) => "book_available",
+            Errors::HoldExists(_) => "hold_exists",
+            Errors::HoldNotFound(_) => "hold_not_found",
+            Errors::FinesOutstanding(_) => "fines_outstanding",
+            Errors::RenewalRefused(_) => "renewal_refused",
+            Errors::IdempotencyInProgress(_) => "idempotency_in_progress",
+            Errors::IdempotencyMismatch(_) => "idempotency_mismatch",
+            Errors::CursorExpired => "cursor_expired",
+            Errors::BadRequest => "bad_request",
+            Errors::Validation(_) | Errors::InvalidFields(_) => "validation_failed",
+            Errors::Conflict(_) => "conflict",
+            Errors::Unauthorized => "unauthorized",
+            Errors::Forbidden(_) => "forbidden",
+            Errors::ServerDown => "server_down",
+            Errors::Transport(_) => "transport_error",
+            Errors::Deserialize(_) => "deserialize_error",
+            Errors::Unknown => "unknown"
+        }
+    }
+}
+
+impl ResponseError for Errors {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            Errors::UserNotFound(_) | Errors::GenreNotFound(_) | Errors::BookNotFound(_) | Errors::LoanNotFound(_) | Errors::CopyNotFound(_) | Errors::HoldNotFound(_) => StatusCode::NOT_FOUND,
+            Errors::GenreExists(_) | Errors::BookOnLoan(_) | Errors::CopyExists(_) | Errors::CopyNotAvailable(_) |
+            Errors::BookOnHold(_) | Errors::BookAvailable(_) | Errors::HoldExists(_) | Errors::FinesOutstanding(_) |
+            Errors::RenewalRefused(_) | Errors::IdempotencyInProgress(_) | Errors::Conflict(_) => StatusCode::CONFLICT,
+            Errors::IdempotencyMismatch(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            Errors::CursorExpired => StatusCode::GONE,
+            Errors::BadRequest | Errors::Validation(_) | Errors::InvalidFields(_) => StatusCode::BAD_REQUEST,
+            Errors::Unauthorized => StatusCode::UNAUTHORIZED,
+            Errors::Forbidden(_) => StatusCode::FORBIDDEN,
+            Errors::ServerDown => StatusCode::SERVICE_UNAVAILABLE,
+            Errors::Transport(_) | Errors::Deserialize(_) => StatusCode::BAD_GATEWAY,
+            Errors::Unknown => StatusCode::INTERNAL_SERVER_ERROR
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let mut body = json!({"error": self.to_string(), "code": self.code()});
+
+        // Eror validasi ikut kirim field mana aja yang salah
+        if let Errors::InvalidFields(fields) = self {
+            body["fields"] = json!(fields);
+        }
+        HttpResponse::build(self.status_code()).json(body)
+    }
+}
+
+/// Nama field yang salah tanpa dobel, urutannya tetap
+fn field_names(fields: &[FieldError]) -> String {
+    let mut names: Vec<&str> = vec![];
+    for f in fields {
+        if !names.contains(&f.field.as_str()) {
+            names.push(&f.field);
+        }
+    }
+    names.join(", ")
+}
+
+/// Waktu disimpan dalam UTC format RFC 3339, misal 2024-01-31T08:00:00Z
+pub fn timestamp(time: DateTime<Utc>) -> String {
+    time.to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+/// Ubah respons gagal dari penyimpanan jadi eror, 404 diganti dengan eror yang dikasih
+pub fn store_error(response: &StoreResponse, not_found: Errors) -> Errors {
+    match response.status_code() {
+        StatusCode::NOT_FOUND => not_found,
+        StatusCode::BAD_REQUEST => Errors::BadRequest,
+        StatusCode::CONFLICT => Errors::Conflict(response.body["error"]["reason"].as_str().unwrap_or("Document already changed").to_string()),
+        StatusCode::SERVICE_UNAVAILABLE => Errors::ServerDown,
+        _ => Errors::Unknown
+    }
+}
+
+/// Ambil daftar dokumen yang gagal dari respons bulk
+pub fn bulk_failures(response: &StoreResponse) -> Result<Vec<Failures>, Errors> {
+    if !response.status_code().is_success() {
+        return Err(store_error(response, Errors::Unknown));
+    }
+
+    // Untuk respons cuma yang gagal yang dikirim
+    let mut fail: Vec<Failures> = vec![];
+    match response.body["errors"].as_bool() {
+        Some(true) => {
+            let items = response.body["items"].as_array().ok_or(Errors::Unknown)?;
+            for (num, dat) in items.iter().enumerate(){
+                // Isinya ada di bawah nama operasinya, index atau create
+                let dat = dat.as_object().and_then(|o| o.values().next()).unwrap_or(&Value::Null);
+                if !dat["error"].is_null(){
+                    fail.push(
+                        Failures {
+                            doc_num: num,
+                            reason: dat["error"]["reason"].as_str().unwrap_or_default().to_string(),
+                            code: dat["status"].as_i64().unwrap_or_default(),
+                            ..Default::default()
+                        }
+                    );
+                }
+            }
+        },
+        Some(false) => (),
+        None => return Err(Errors::Unknown)
+    }
+    Ok(fail)
+}
+
 // Ambil buku
-pub async fn get_book(genre: &str, book_id: &str, retrieve_fields: Option<String>, db: &Database) -> Result<(StatusCode, Value), (StatusCode, Errors)>{
+pub async fn get_book(genre: &str, book_id: &str, retrieve_fields: Option<String>, db: &dyn LibraryStore) -> Result<Value, Errors>{
 
     // Kirim permintaan ke elastic
-    let response = db.get_single_document(genre, book_id, retrieve_fields).await.unwrap();
-    
+    let response = db.get_single_document(genre, book_id, retrieve_fields).await?;
+
     // Kalo ga ketemu kasih eror
     if !response.status_code().is_success() {
-        let e = match response.status_code(){
-            StatusCode::NOT_FOUND => Errors::BookNotFound(book_id.to_string()),
-            _ => Errors::Unknown
-        };
-        return Err((response.status_code(), e));
+        return Err(store_error(&response, Errors::BookNotFound(book_id.to_string())));
     }
 
-    Ok((response.status_code(), response.json::<Value>().await.unwrap()))
+    Ok(response.body)
 }
 
 // Buat genre baru
-pub async fn create_new_genre(user_id: Option<String>, genre: &str, db: &Database) {
-    // Kalo ada user idnya berarti ini mau masukin ke user, kalo engga, ini berarti mau bikin user
-    let genre_index = match user_id {
-        Some(x) => format!("{}.{}", x.to_lowercase(), &genre.to_lowercase()),
-        None => genre.to_string()
-    };
-
-    // Bikin format data yang mau dikirim ke server untuk buat genre baru
-    if db.get_indices(Some(genre_index.clone())).await.unwrap().status_code() == StatusCode::NOT_FOUND {
-        let body = 
-            json!(
-                {
-                    "mappings": { 	
-                        "dynamic":"true",
-                        "properties": {
-                            "tanggal_terbit": {
-                                "type": "date",
-                                "format": "dd-MM-yyyy"
-                            }
-                        }
-                    }
-                }
-            );
-        db.create_single_index(&genre_index, &body).await.unwrap();
+pub async fn create_new_genre(user_id: Option<String>, genre: &str, cfg: &Config, db: &dyn LibraryStore) -> Result<(), Errors> {
+    // Kalo ada user idnya berarti ini mau masukin ke user, kalo engga, ini berarti mau bikin user.
+    // Indeks genre ikut index template, indeks user pakai mappingnya sendiri
+    match user_id {
+        Some(x) => create_book_index(&book_index(&x, &genre.to_lowercase()), cfg, db).await,
+        None => create_index_if_missing(genre, &user_index_body(), db).await
     }
 }
 
+/// Buat indeks kalo belum ada
+pub async fn create_index_if_missing(index: &str, body: &Value, db: &dyn LibraryStore) -> Result<(), Errors> {
+    if db.get_indices(Some(index.to_string())).await?.status_code() == StatusCode::NOT_FOUND {
+        let response = db.create_single_index(index, body).await?;
+
+        // Kalo udah dibuat duluan sama permintaan lain, anggap berhasil
+        if !response.status_code().is_success() && response.body["error"]["type"] != "resource_already_exists_exception" {
+            return Err(store_error(&response, Errors::Unknown));
+        }
+    }
+    Ok(())
+}
+
 // Cek kalo genre ada
-pub async fn genre_exists(user_id: &str, genre: &str, db: &Database) -> Result<HashSet<String>, (StatusCode, Errors, HashSet<String>)> {
-    match get_user_genre_list(user_id, db).await {
-        Ok(l) => {
-            // Cek kalo user punya genrenya
-            match l.contains(genre) {
-                true => Ok(l),
-                false => Err((StatusCode::NOT_FOUND, Errors::GenreNotFound(genre.to_string()), l))
-            }
-        },
-        Err((s, e)) => Err((s, e, HashSet::new()))
+pub async fn genre_exists(user_id: &str, genre: &str, cfg: &Config, db: &dyn LibraryStore) -> Result<HashSet<String>, Errors> {
+    let l = get_user_genre_list(user_id, cfg, db).await?;
+
+    // Cek kalo user punya genrenya
+    match l.contains(genre) {
+        true => Ok(l),
+        false => Err(Errors::GenreNotFound(genre.to_string()))
     }
 }
 
 /// Ambil list genre dari user
-pub async fn get_user_genre_list(user_id: &str, db: &Database) -> Result<HashSet<String>, (StatusCode, Errors)> {
-    match get_book(USER_LIST, user_id, Some("genres".to_string()), db).await{
-        Ok((_, v)) => {
+pub async fn get_user_genre_list(user_id: &str, cfg: &Config, db: &dyn LibraryStore) -> Result<HashSet<String>, Errors> {
+    match get_book(&cfg.library.user_index, user_id, Some("genres".to_string()), db).await{
+        Ok(v) => {
             match v.get("genres") {
-                Some(x) => Ok(serde_json::from_value(json!(x)).unwrap()),
+                Some(x) => Ok(serde_json::from_value(x.clone())?),
                 None => Ok(HashSet::new())
             }
         },
-        Err((code, _)) => match code {
-            StatusCode::NOT_FOUND => Err((code, Errors::UserNotFound(user_id.to_string()))),
-            _ => Err((code, Errors::Unknown))
-        },
+        Err(Errors::BookNotFound(_)) => Err(Errors::UserNotFound(user_id.to_string())),
+        Err(e) => Err(e)
     }
 }
 
 // Cek kalo server hidup
-pub async fn check_server(db: &Database) -> bool {
-    db.get_indices(Some("".to_string())).await.is_ok()
+pub async fn check_server(db: &dyn LibraryStore) -> Result<(), Errors> {
+    match db.get_indices(Some("".to_string())).await {
+        Ok(_) => Ok(()),
+        Err(_) => Err(Errors::ServerDown)
+    }
 }
 
 // Cek kalo user sama genre ada
-pub async fn check_userid_genre(user_id: &str, genre: &str, db: &Database) -> Result<(), (StatusCode, Errors)>{
-    if check_server(db).await {
-        match genre_exists(user_id, genre, db).await {
-            Ok(_) => return Ok(()),
-            Err((s, e, _)) => return Err((s, e))
-        }
-    }
-    Err((StatusCode::SERVICE_UNAVAILABLE, Errors::ServerDown))
-}
\ No newline at end of file
+pub async fn check_userid_genre(user_id: &str, genre: &str, cfg: &Config, db: &dyn LibraryStore) -> Result<(), Errors>{
+    check_server(db).await?;
+    genre_exists(user_id, genre, cfg, db).await?;
+    Ok(())
+}
@@ -0,0 +1,301 @@
+use actix_web::{http::StatusCode, web::{self, Data}, HttpResponse};
+use chrono::{DateTime, Duration, Utc};
+use serde_json::{json, Value};
+use crate::{auth::Caller, config::Config, copy::*, fine::*, hold::*, mapping::book_index, store::LibraryStore, libs::*};
+use super::structs::*;
+
+/// Id peminjaman yang masih jalan, satu buku atau eksemplar cuma bisa punya satu jadi ga bisa dipinjam dua kali
+fn active_loan_id(user_id: &str, genre: &str, book_id: &str, barcode: Option<&str>) -> String {
+    match barcode {
+        Some(b) => format!("{}:{}:{}:{}", user_id, genre, book_id, b),
+        None => format!("{}:{}:{}", user_id, genre, book_id)
+    }
+}
+
+/// Bentuk peminjaman yang dikirim ke client, ditambah id dan status telat
+fn loan_view(loan_id: &str, loan: &Value, now: DateTime<Utc>) -> Value {
+    let due_at = loan["due_at"].as_str().and_then(|d| DateTime::parse_from_rfc3339(d).ok());
+    let overdue = loan["returned_at"].is_null() && due_at.map(|d| d < now).unwrap_or(false);
+    json!({
+        "loan_id": loan_id,
+        "library": loan["library"],
+        "genre": loan["genre"],
+        "book_id": loan["book_id"],
+        "barcode": loan["barcode"],
+        "borrower": loan["borrower"],
+        "loaned_at": loan["loaned_at"],
+        "due_at": loan["due_at"],
+        "returned_at": loan["returned_at"],
+        "overdue": overdue,
+        "renewal_count": loan["renewal_count"].as_i64().unwrap_or(0),
+        "renewals": loan["renewals"].as_array().cloned().unwrap_or_default()
+    })
+}
+
+/// Indeks peminjaman harus selalu ada
+async fn create_loan_index(cfg: &Config, db: &dyn LibraryStore) -> Result<(), Errors> {
+    let body =
+        json!(
+            {
+                "mappings": {
+                    "properties": {
+                        "library": {"type": "keyword"},
+                        "genre": {"type": "keyword"},
+                        "book_id": {"type": "keyword"},
+                        "barcode": {"type": "keyword"},
+                        "borrower": {"type": "keyword"},
+                        "loaned_at": {"type": "date"},
+                        "due_at": {"type": "date"},
+                        "returned_at": {"type": "date"},
+                        "renewal_count": {"type": "integer"},
+                        "renewals": {
+                            "properties": {
+                                "renewed_at": {"type": "date"},
+                                "previous_due_at": {"type": "date"},
+                                "due_at": {"type": "date"}
+                            }
+                        }
+                    }
+                }
+            }
+        );
+    create_index_if_missing(&cfg.library.loan_index, &body, db).await
+}
+
+/// Cek kalo buku tanpa eksemplar sedang dipinjam
+pub async fn is_on_loan(user_id: &str, genre: &str, book_id: &str, cfg: &Config, db: &dyn LibraryStore) -> Result<bool, Errors> {
+    let response = db.get_single_document(&cfg.library.loan_index, &active_loan_id(user_id, genre, book_id, None), Some("book_id".to_string())).await?;
+    match response.status_code() {
+        s if s.is_success() => Ok(true),
+        StatusCode::NOT_FOUND => Ok(false),
+        _ => Err(store_error(&response, Errors::Unknown))
+    }
+}
+
+/// Pinjamkan satu buku
+pub async fn checkout_book(path: web::Path<UserBookID>, data: web::Json<LoanInput>, cfg: Data<Config>, db: Data::<dyn LibraryStore>) -> Result<HttpResponse, Errors> {
+    // Cek kalo user sama genre ada
+    let genre = path.genre.to_lowercase();
+    check_userid_genre(&path.user_id, &genre, &cfg, db.get_ref()).await?;
+
+    // Peminjam dan lama pinjam harus masuk akal
+    let borrower = data.borrower.trim();
+    if borrower.is_empty() {
+        return Err(Errors::Validation("borrower cannot be empty".to_string()));
+    }
+    let days = data.days.unwrap_or(cfg.library.loan_days);
+    if !(1..=365).contains(&days) {
+        return Err(Errors::Validation("days must be between 1 and 365".to_string()));
+    }
+
+    // Bukunya harus ada dan peminjamnya ga punya tunggakan denda
+    get_book(&book_index(&path.user_id, &genre), &path.book_id, Some("judul".to_string()), db.get_ref()).await?;
+    check_fines(&path.user_id, borrower, &cfg, db.get_ref()).await?;
+
+    // Pesanan yang lewat batas ambil dilepas dulu supaya statusnya benar
+    expire_holds(&path.user_id, Some((&genre, &path.book_id)), &cfg, db.get_ref()).await?;
+
+    // Kalo bukunya punya eksemplar, yang dipinjam harus eksemplar yang tersedia
+    let barcode = data.barcode.as_deref().map(str::trim).filter(|b| !b.is_empty());
+    match barcode {
+        Some(b) => {
+            let copy = get_copy(&path.user_id, b, &cfg, db.get_ref()).await?;
+            if copy["genre"] != genre.as_str() || copy["book_id"] != path.book_id.as_str() {
+                return Err(Errors::CopyNotFound(b.to_string()));
+            }
+            if copy["status"] != json!(CopyStatus::Available) && copy["status"] != json!(CopyStatus::OnHold) {
+                return Err(Errors::CopyNotAvailable(b.to_string()));
+            }
+        },
+        None => if count_copies(&path.user_id, &genre, &path.book_id, &cfg, db.get_ref()).await? > 0 {
+            return Err(Errors::Validation("barcode is required, this book has copies".to_string()));
+        }
+    }
+
+    // Buku atau eksemplar yang sudah disiapkan untuk pemesan cuma boleh dipinjam pemesannya
+    let hold = ready_hold(&path.user_id, &genre, &path.book_id, barcode, &cfg, db.get_ref()).await?;
+    if let Some((_, h)) = &hold {
+        if h["patron"] != borrower {
+            return Err(match barcode {
+                Some(b) => Errors::CopyNotAvailable(b.to_string()),
+                None => Errors::BookOnHold(path.book_id.to_string())
+            });
+        }
+    }
+
+    // Simpan dengan id tetap, kalo bukunya masih dipinjam elastic nolak dengan 409
+    create_loan_index(&cfg, db.get_ref()).await?;
+    let now = Utc::now();
+    let loan_id = active_loan_id(&path.user_id, &genre, &path.book_id, barcode);
+    let mut loan = json!({
+        "library": path.user_id,
+        "genre": genre,
+        "book_id": path.book_id,
+        "borrower": borrower,
+        "loaned_at": timestamp(now),
+        "due_at": timestamp(now + Duration::days(days))
+    });
+    if let Some(b) = barcode {
+        loan["barcode"] = json!(b);
+    }
+    let response = db.create_document(&cfg.library.loan_index, &loan_id, &loan).await?;
+    match response.status_code() {
+        s if s.is_success() => (),
+        StatusCode::CONFLICT => return Err(Errors::BookOnLoan(path.book_id.to_string())),
+        _ => return Err(store_error(&response, Errors::Unknown))
+    }
+    if let Some(b) = barcode {
+        set_copy_status(&path.user_id, b, CopyStatus::OnLoan, &cfg, db.get_ref()).await?;
+    }
+    if let Some((hold_id, h)) = hold {
+        fulfil_hold(&hold_id, h, &cfg, db.get_ref()).await?;
+    }
+    Ok(HttpResponse::Created().json(loan_view(&loan_id, &loan, now)))
+}
+
+/// Kembalikan buku yang dipinjam
+pub async fn checkin_book(path: web::Path<UserBookID>, query: web::Query<OptionalBarcode>, cfg: Data<Config>, db: Data::<dyn LibraryStore>) -> Result<HttpResponse, Errors> {
+    // Cek kalo elastic hidup
+    check_server(db.get_ref()).await?;
+
+    // Ambil peminjaman yang masih jalan
+    let genre = path.genre.to_lowercase();
+    let barcode = query.barcode.as_deref().map(str::trim).filter(|b| !b.is_empty());
+    let loan_id = active_loan_id(&path.user_id, &genre, &path.book_id, barcode);
+    let response = db.get_single_document(&cfg.library.loan_index, &loan_id, None).await?;
+    if !response.status_code().is_success() {
+        return Err(store_error(&response, Errors::LoanNotFound(path.book_id.to_string())));
+    }
+
+    // Simpan ke riwayat dulu baru hapus yang aktif, kalo gagal di tengah tinggal diulang
+    let now = Utc::now();
+    let mut loan = response.body;
+    loan["returned_at"] = json!(timestamp(now));
+    let history_id = format!("{}:{}", loan_id, loan["loaned_at"].as_str().unwrap_or_default());
+    let response = db.create_document(&cfg.library.loan_index, &history_id, &loan).await?;
+    if !response.status_code().is_success() && response.status_code() != StatusCode::CONFLICT {
+        return Err(store_error(&response, Errors::Unknown));
+    }
+    let fine = charge_late_return(&history_id, &loan, now, &cfg, db.get_ref()).await?;
+    let response = db.delete_single_document(&cfg.library.loan_index, &loan_id).await?;
+    if !response.status_code().is_success() && response.status_code() != StatusCode::NOT_FOUND {
+        return Err(store_error(&response, Errors::Unknown));
+    }
+
+    // Kasih ke pemesan berikutnya, kalo ga ada yang antre eksemplarnya bisa dipinjam lagi
+    promote_next(&path.user_id, &genre, &path.book_id, barcode, &cfg, db.get_ref()).await?;
+
+    let mut view = loan_view(&history_id, &loan, now);
+    view["fine"] = json!(fine);
+    Ok(HttpResponse::Ok().json(view))
+}
+
+/// Perpanjang peminjaman yang masih jalan
+pub async fn renew_loan(path: web::Path<UserBookID>, query: web::Query<OptionalBarcode>, caller: Caller, cfg: Data<Config>, db: Data::<dyn LibraryStore>) -> Result<HttpResponse, Errors> {
+    // Cek kalo elastic hidup
+    check_server(db.get_ref()).await?;
+
+    // Ambil peminjaman yang masih jalan, member cuma boleh perpanjang pinjamannya sendiri
+    let genre = path.genre.to_lowercase();
+    let barcode = query.barcode.as_deref().map(str::trim).filter(|b| !b.is_empty());
+    let loan_id = active_loan_id(&path.user_id, &genre, &path.book_id, barcode);
+    let response = db.get_single_document(&cfg.library.loan_index, &loan_id, None).await?;
+    if !response.status_code().is_success() {
+        return Err(store_error(&response, Errors::LoanNotFound(path.book_id.to_string())));
+    }
+    let mut loan = response.body;
+    let borrower = loan["borrower"].as_str().unwrap_or_default().to_string();
+    caller.require_patron(&borrower)?;
+
+    // Cek batas perpanjangan dan antrean orang lain
+    let renewal_count = loan["renewal_count"].as_i64().unwrap_or(0);
+    if renewal_count >= cfg.library.max_renewals {
+        return Err(Errors::RenewalRefused(format!("already renewed {} of {} times", renewal_count, cfg.library.max_renewals)));
+    }
+    if has_waiting_holds(&path.user_id, &genre, &path.book_id, &borrower, &cfg, db.get_ref()).await? {
+        return Err(Errors::RenewalRefused("another patron has a hold on this book".to_string()));
+    }
+
+    // Yang sudah telat harus dikembalikan dulu supaya dendanya tetap dihitung sampai hari kembali
+    let now = Utc::now();
+    let due_at = loan["due_at"].as_str().and_then(|d| DateTime::parse_from_rfc3339(d).ok()).map(|d| d.with_timezone(&Utc)).unwrap_or(now);
+    if due_at <= now {
+        return Err(Errors::RenewalRefused("the loan is overdue, return the book first".to_string()));
+    }
+
+    // Peminjam yang diblokir karena denda ga boleh perpanjang, sama seperti pinjam
+    check_fines(&path.user_id, &borrower, &cfg, db.get_ref()).await?;
+
+    // Tambah dari tanggal kembali
+    let new_due_at = due_at + Duration::days(cfg.library.renewal_days);
+    let mut renewals = loan["renewals"].as_array().cloned().unwrap_or_default();
+    renewals.push(json!({
+        "renewed_at": timestamp(now),
+        "previous_due_at": loan["due_at"],
+        "due_at": timestamp(new_due_at)
+    }));
+    let changes = json!({
+        "due_at": timestamp(new_due_at),
+        "renewal_count": renewal_count + 1,
+        "renewals": renewals
+    });
+    let response = db.update_single_document(&cfg.library.loan_index, &loan_id, &changes).await?;
+    if !response.status_code().is_success() {
+        return Err(store_error(&response, Errors::LoanNotFound(path.book_id.to_string())));
+    }
+
+    for (k, v) in changes.as_object().into_iter().flatten() {
+        loan[k] = v.clone();
+    }
+    Ok(HttpResponse::Ok().json(loan_view(&loan_id, &loan, now)))
+}
+
+/// Ambil list peminjaman satu library, yang masih jalan, yang telat atau yang sudah kembali
+pub async fn get_loans(path: web::Path<UserID>, query: web::Query<LoanQuery>, cfg: Data<Config>, db: Data::<dyn LibraryStore>) -> Result<HttpResponse, Errors> {
+    // Cek kalo elastic hidup dan usernya ada
+    check_server(db.get_ref()).await?;
+    get_user_genre_list(&path.user_id, &cfg, db.get_ref()).await?;
+    create_loan_index(&cfg, db.get_ref()).await?;
+
+    // Buat filternya sesuai status
+    let now = Utc::now();
+    let mut filter = vec![json!({"term": {"library": path.user_id}})];
+    let mut must_not = vec![];
+    match query.status.unwrap_or_default() {
+        LoanStatus::Active => must_not.push(json!({"exists": {"field": "returned_at"}})),
+        LoanStatus::Overdue => {
+            must_not.push(json!({"exists": {"field": "returned_at"}}));
+            filter.push(json!({"range": {"due_at": {"lt": timestamp(now)}}}));
+        },
+        LoanStatus::Returned => filter.push(json!({"exists": {"field": "returned_at"}}))
+    }
+    if let Some(borrower) = &query.borrower {
+        filter.push(json!({"term": {"borrower": borrower}}));
+    }
+    let body = json!({
+        "query": {
+            "bool": {
+                "filter": filter,
+                "must_not": must_not
+            }
+        },
+        "sort": [{"due_at": "asc"}]
+    });
+
+    // Kirim permintaan cari
+    let count = query.count.unwrap_or(cfg.library.default_page_size);
+    let response = db.search(&cfg.library.loan_index, &body, query.from, Some(count)).await?;
+    if !response.status_code().is_success() {
+        return Err(store_error(&response, Errors::Unknown));
+    }
+
+    let data: Vec<Value> = response.body["hits"]["hits"].as_array().map(|hits| hits.iter().map(|h| {
+        loan_view(h["_id"].as_str().unwrap_or_default(), &h["_source"], now)
+    }).collect()).unwrap_or_default();
+    Ok(HttpResponse::Ok().json(json!({
+        "data": data,
+        "total": &response.body["hits"]["total"]["value"],
+        "from": &query.from.unwrap_or(0),
+        "count": &count
+    })))
+}
@@ -1,73 +1,622 @@
-use actix_web::{web::{self, Data}, App, HttpServer};
+use std::sync::Arc;
+use actix_web::{middleware::from_fn, web::{self, Data}, App, HttpServer};
+use auth::{authenticate, can_act_as_patron, can_edit_library, can_manage_account, can_manage_users, can_read_library};
+use libs::Errors;
 use actix_cors::Cors;
+use config::{Backend, Config};
 use database::Database;
+use memory::MemoryStore;
+use store::LibraryStore;
 use user::*;
 use genre::*;
 use book::*;
+use loan::*;
+use copy::*;
+use hold::*;
+use fine::*;
 
+mod auth;
+mod config;
 mod database;
+mod memory;
+mod store;
 mod book;
+mod loan;
+mod copy;
+mod cursor;
+mod duplicate;
+mod facet;
+mod filter;
+mod hold;
+mod isbn;
+mod mapping;
+mod fine;
 mod genre;
+mod idempotency;
 mod user;
+mod sort;
+mod validate;
 mod structs;
 mod libs;
 
-/// Nama list utama untuk setor list usernya
-pub const USER_LIST: &str = "users_apps";
+/// Semua route API, dipakai server dan tes
+fn routes(app: &mut web::ServiceConfig) {
+    app
+    // Eror dari input yang salah juga dikirim dengan bentuk eror yang sama
+    .app_data(web::JsonConfig::default().error_handler(|e, _| Errors::Validation(e.to_string()).into()))
+    .app_data(web::QueryConfig::default().error_handler(|e, _| Errors::Validation(e.to_string()).into()))
+    .app_data(web::PathConfig::default().error_handler(|e, _| Errors::Validation(e.to_string()).into()))
+    .service(
+        // Semua route harus pakai API key, lalu izinnya dicek per route sesuai peran user
+        web::scope("")
+            .wrap(from_fn(authenticate))
+            .service(
+                // Route untuk user
+                web::scope("/user")
+                    .route("", web::post().to(create_new_user).wrap(from_fn(can_manage_users)))
+                    .route("", web::put().to(update_user))
+                    .route("/{user_id}", web::get().to(get_a_user).wrap(from_fn(can_manage_account)))
+                    .route("/{user_id}", web::delete().to(delete_user).wrap(from_fn(can_manage_users)))
+                    .route("/{user_id}/key", web::post().to(rotate_api_key).wrap(from_fn(can_manage_account)))
+            )
+
+            // Ambil list user
+            .route("/users", web::get().to(get_user_list).wrap(from_fn(can_manage_users)))
+
+            // Route untuk genre
+            .service(
+                web::scope("/genre/{user_id}")
+                    .route("", web::post().to(create_genre).wrap(from_fn(can_edit_library)))
+                    .route("", web::get().to(get_genre).wrap(from_fn(can_read_library)))
+                    .route("/{genre}", web::delete().to(delete_genre).wrap(from_fn(can_edit_library)))
+            )
+
+            // Route untuk ambil buku
+            .service(
+                web::scope("/book/{user_id}/{genre}")
+                    .route("", web::post().to(create_books).wrap(from_fn(can_edit_library)))
+                    .route("/{book_id}", web::get().to(get_book).wrap(from_fn(can_read_library)))
+                    .route("/{book_id}", web::put().to(update_book).wrap(from_fn(can_edit_library)))
+                    .route("/{book_id}", web::delete().to(delete_book).wrap(from_fn(can_edit_library)))
+            )
+
+            // Cari buku pakai isbn di semua genre
+            .route("/isbn/{user_id}/{isbn}", web::get().to(get_book_by_isbn).wrap(from_fn(can_read_library)))
+
+            // Cari
+            .service(
+                web::scope("/search/{user_id}")
+                    .wrap(from_fn(can_read_library))
+                    .route("", web::post().to(search_books))
+                    .route("", web::get().to(search_books_get))
+            )
+
+            // Saran judul dan penulis waktu mengetik
+            .route("/suggest/{user_id}", web::get().to(suggest_books).wrap(from_fn(can_read_library)))
+
+            // Route untuk pinjam dan kembalikan buku
+            .service(
+                web::scope("/loan/{user_id}")
+                    .route("", web::get().to(get_loans).wrap(from_fn(can_read_library)))
+                    .route("/{genre}/{book_id}", web::post().to(checkout_book).wrap(from_fn(can_edit_library)))
+                    .route("/{genre}/{book_id}/return", web::post().to(checkin_book).wrap(from_fn(can_edit_library)))
+                    .route("/{genre}/{book_id}/renew", web::post().to(renew_loan).wrap(from_fn(can_act_as_patron)))
+            )
+
+            // Route untuk eksemplar fisik buku
+            .service(
+                web::scope("/copy/{user_id}")
+                    .route("/{genre}/{book_id}", web::post().to(add_copy).wrap(from_fn(can_edit_library)))
+                    .route("/{genre}/{book_id}", web::get().to(list_copies).wrap(from_fn(can_read_library)))
+                    .route("/{barcode}", web::get().to(get_copy_by_barcode).wrap(from_fn(can_read_library)))
+                    .route("/{barcode}", web::put().to(update_copy).wrap(from_fn(can_edit_library)))
+                    .route("/{barcode}", web::delete().to(retire_copy).wrap(from_fn(can_edit_library)))
+            )
+
+            // Route untuk antrean pesanan buku
+            .service(
+                web::scope("/hold/{user_id}")
+                    .route("", web::get().to(get_holds).wrap(from_fn(can_read_library)))
+                    .route("/{genre}/{book_id}", web::post().to(place_hold).wrap(from_fn(can_act_as_patron)))
+                    .route("/{genre}/{book_id}", web::get().to(get_book_holds).wrap(from_fn(can_read_library)))
+                    .route("/{genre}/{book_id}", web::delete().to(cancel_hold).wrap(from_fn(can_act_as_patron)))
+            )
+
+            // Route untuk denda peminjam
+            .service(
+                web::scope("/fine/{user_id}/{borrower}")
+                    .route("", web::get().to(get_fines).wrap(from_fn(can_read_library)))
+                    .route("/payment", web::post().to(pay_fine).wrap(from_fn(can_edit_library)))
+                    .route("/waiver", web::post().to(waive_fine).wrap(from_fn(can_edit_library)))
+            )
+
+            // Upload
+            .route("/upload/{user_id}/{genre}", web::post().to(upload_json).wrap(from_fn(can_edit_library)))
+    );
+}
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
-    // Debug mode
-    std::env::set_var("RUST_LOG", "debug");
-    env_logger::init();
+    // Baca konfigurasi, kalo salah langsung berhenti
+    let config = match Config::load() {
+        Ok(c) => c,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+    env_logger::Builder::new().parse_filters(&config.server.log_level).init();
+
+    // Pilih penyimpanan, elastic atau di memori
+    let store: Arc<dyn LibraryStore> = match config.database.backend {
+        Backend::Memory => Arc::new(MemoryStore::new()),
+        Backend::Elasticsearch => match Database::new(&config.database) {
+            Ok(d) => Arc::new(d),
+            Err(e) => {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+        }
+    };
+
+    // Perintah migrate pindahkan indeks genre lama ke mapping terbaru lalu berhenti
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match args.first().map(String::as_str) {
+        None => (),
+        Some("migrate") => {
+            let dry_run = args.iter().any(|a| a == "--dry-run");
+            if let Err(e) = mapping::migrate(dry_run, &config, store.as_ref()).await {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+            return Ok(());
+        },
+        Some(other) => {
+            eprintln!("Unknown command {}, usage: library_app [migrate [--dry-run]]", other);
+            std::process::exit(2);
+        }
+    }
 
-    let db = Data::new(Database::new("http://127.0.0.1:9200"));
+    // Daftarkan index template genre, kalo elastic belum hidup didaftarkan waktu bikin genre pertama
+    if let Err(e) = mapping::register_book_template(&config, store.as_ref()).await {
+        log::warn!("Cannot register book index template yet: {}", e);
+    }
+    let db: Data<dyn LibraryStore> = Data::from(store);
+    let cfg = Data::new(config.clone());
 
     // Hidupin servernya
-    HttpServer::new( move || {
+    let server = HttpServer::new( move || {
+        // Kalo ada "*" semua origin boleh, kalo engga cuma yang ada di daftar
+        let cors = if cfg.server.cors_origins.iter().any(|o| o == "*") {
+            Cors::permissive()
+        } else {
+            cfg.server.cors_origins.iter()
+                .fold(Cors::default(), |c, o| c.allowed_origin(o))
+                .allow_any_method()
+                .allow_any_header()
+        };
+
         App::new()
-        .wrap(Cors::permissive())
-        .service(
-            web::scope("")
-                .app_data(db.clone())
-                .service(
-                    // Route untuk user
-                    web::scope("/user")
-                        .route("", web::post().to(create_new_user))
-                        .route("", web::put().to(update_user))   
-                        .route("/{user_id}", web::get().to(get_a_user))
-                        .route("/{user_id}", web::delete().to(delete_user))
-                )
-
-                // Ambil list user
-                .route("/users", web::get().to(get_user_list))
-                
-                // Route untuk genre
-                .service(
-                    web::scope("/genre/{user_id}")
-                        .route("", web::post().to(create_genre))
-                        .route("", web::get().to(get_genre))
-                        .route("/{genre}", web::delete().to(delete_genre))
-                )
-                
-                // Route untuk ambil buku
-                .service(
-                    web::scope("/book/{user_id}/{genre}")
-                        .route("", web::post().to(create_books))
-                        .route("/{book_id}", web::get().to(get_book))
-                        .route("/{book_id}", web::put().to(update_book))
-                        .route("/{book_id}", web::delete().to(delete_book))
-                )
-
-                // Cari
-                .route("/search/{user_id}", web::post().to(search_books))
-                .route("/search/{user_id}", web::get().to(search_books_get))   
-                
-                // Upload
-                .route("/upload/{user_id}/{genre}", web::post().to(upload_json))
-        )
-        })
-    .bind(("127.0.0.1", 1234))?
-    .run()
-    .await
-}
\ No newline at end of file
+        .wrap(cors)
+        .app_data(db.clone())
+        .app_data(cfg.clone())
+        .configure(routes)
+        });
+
+    // Jumlah worker bawaan actix ikut jumlah core, kecuali diatur
+    let server = match config.server.workers {
+        Some(n) => server.workers(n),
+        None => server
+    };
+    server
+        .bind((config.server.bind_address.as_str(), config.server.port))?
+        .run()
+        .await
+}
+#[cfg(test)]
+mod tests {
+    use actix_http::Request;
+    use actix_web::{body::{self, MessageBody}, dev::{Service, ServiceResponse}, http::{Method, StatusCode}, test::{self, TestRequest}};
+    use serde_json::{json, Value};
+    use super::*;
+
+    const ADMIN_KEY: &str = "kunci-admin-buat-tes";
+
+    /// Server lengkap di atas MemoryStore, sama seperti main
+    async fn app() -> impl Service<Request, Response = ServiceResponse<impl MessageBody>, Error = actix_web::Error> {
+        app_with(Arc::new(MemoryStore::new())).await
+    }
+
+    /// Sama seperti app, tapi penyimpanannya dipegang tes juga
+    async fn app_with(store: Arc<dyn LibraryStore>) -> impl Service<Request, Response = ServiceResponse<impl MessageBody>, Error = actix_web::Error> {
+        let mut config = Config::default();
+        config.auth.admin_api_key = Some(ADMIN_KEY.to_string());
+        mapping::register_book_template(&config, store.as_ref()).await.unwrap();
+        test::init_service(
+            App::new()
+            .app_data(Data::from(store))
+            .app_data(Data::new(config))
+            .configure(routes)
+        ).await
+    }
+
+    /// Kirim satu request, eror dari middleware diubah jadi respons seperti di server
+    async fn send<B: MessageBody>(app: &impl Service<Request, Response = ServiceResponse<B>, Error = actix_web::Error>, method: Method, uri: &str, key: &str, data: Option<Value>) -> (StatusCode, Value) {
+        let mut req = TestRequest::default().method(method).uri(uri).insert_header(("X-API-Key", key));
+        if let Some(d) = data {
+            req = req.set_json(d);
+        }
+        let (status, bytes) = match app.call(req.to_request()).await {
+            Ok(res) => (res.status(), body::to_bytes(res.into_body()).await.ok().unwrap_or_default()),
+            Err(e) => {
+                let res = e.error_response();
+                (res.status(), body::to_bytes(res.into_body()).await.ok().unwrap_or_default())
+            }
+        };
+        (status, serde_json::from_slice(&bytes).unwrap_or(Value::Null))
+    }
+
+    /// Bikin user lewat API, hasilnya (user_id, api_key)
+    async fn create_user<B: MessageBody>(app: &impl Service<Request, Response = ServiceResponse<B>, Error = actix_web::Error>, name: &str, role: &str) -> (String, String) {
+        let (status, body) = send(app, Method::POST, "/user", ADMIN_KEY, Some(json!({"user_name": name, "role": role}))).await;
+        assert_eq!(status, StatusCode::CREATED);
+        (body["user_id"].as_str().unwrap().to_string(), body["api_key"].as_str().unwrap().to_string())
+    }
+
+    /// Library dengan satu genre novel dan dua buku, hasilnya (user_id, api_key, id buku pertama)
+    async fn library<B: MessageBody>(app: &impl Service<Request, Response = ServiceResponse<B>, Error = actix_web::Error>) -> (String, String, String) {
+        let (user_id, key) = create_user(app, "perpus", "member").await;
+        let (status, _) = send(app, Method::POST, &format!("/genre/{}", user_id), ADMIN_KEY, Some(json!({"genre": "novel"}))).await;
+        assert_eq!(status, StatusCode::CREATED);
+        let books = json!([
+            {"judul": "Laskar Pelangi", "penulis": "Andrea Hirata", "isbn": "9789793062792", "genre": ["fiksi"], "bahasa": "id", "jumlah_halaman": 529},
+            {"judul": "Émile", "penulis": "Rousseau", "genre": ["filsafat"], "bahasa": "fr", "jumlah_halaman": 200}
+        ]);
+        let (status, _) = send(app, Method::POST, &format!("/book/{}/novel", user_id), ADMIN_KEY, Some(books)).await;
+        assert_eq!(status, StatusCode::OK);
+        let (_, found) = send(app, Method::GET, &format!("/search/{}?judul=Laskar%20Pelangi", user_id), ADMIN_KEY, None).await;
+        let book_id = found["data"][0]["_id"].as_str().unwrap().to_string();
+        (user_id, key, book_id)
+    }
+
+    #[actix_web::test]
+    async fn user_crud() {
+        let app = app().await;
+        let (user_id, key) = create_user(&app, "budi", "member").await;
+
+        let (status, body) = send(&app, Method::GET, &format!("/user/{}", user_id), &key, None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["name"], "budi");
+        assert_eq!(body["role"], "member");
+        assert!(body.get("api_key_hash").is_none());
+
+        let (status, _) = send(&app, Method::PUT, "/user", &key, Some(json!({"user_id": user_id, "user_name": "budi santoso"}))).await;
+        assert_eq!(status, StatusCode::OK);
+        let (status, body) = send(&app, Method::GET, "/users?user_name=santoso", ADMIN_KEY, None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body[0]["_source"]["name"], "budi santoso");
+
+        // Key lama ga berlaku lagi setelah diganti
+        let (status, body) = send(&app, Method::POST, &format!("/user/{}/key", user_id), &key, None).await;
+        assert_eq!(status, StatusCode::OK);
+        let new_key = body["api_key"].as_str().unwrap().to_string();
+        assert_eq!(send(&app, Method::GET, &format!("/user/{}", user_id), &key, None).await.0, StatusCode::UNAUTHORIZED);
+        assert_eq!(send(&app, Method::GET, &format!("/user/{}", user_id), &new_key, None).await.0, StatusCode::OK);
+
+        let (status, _) = send(&app, Method::DELETE, &format!("/user/{}", user_id), ADMIN_KEY, None).await;
+        assert_eq!(status, StatusCode::OK);
+        let (status, body) = send(&app, Method::GET, &format!("/user/{}", user_id), ADMIN_KEY, None).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(body["code"], "user_not_found");
+    }
+
+    #[actix_web::test]
+    async fn genre_crud() {
+        let app = app().await;
+        let (user_id, _) = create_user(&app, "perpus", "member").await;
+        let uri = format!("/genre/{}", user_id);
+
+        assert_eq!(send(&app, Method::POST, &uri, ADMIN_KEY, Some(json!({"genre": "Fiksi Ilmiah"}))).await.0, StatusCode::CREATED);
+        let (status, body) = send(&app, Method::POST, &uri, ADMIN_KEY, Some(json!({"genre": "fiksi ilmiah"}))).await;
+        assert_eq!(status, StatusCode::CONFLICT);
+        assert_eq!(body["code"], "genre_exists");
+
+        let (status, body) = send(&app, Method::GET, &format!("{}?genre=fiksi_ilmiah", uri), ADMIN_KEY, None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body[0]["index"], mapping::book_index(&user_id, "fiksi_ilmiah"));
+
+        assert_eq!(send(&app, Method::DELETE, &format!("{}/fiksi_ilmiah", uri), ADMIN_KEY, None).await.0, StatusCode::OK);
+        let (status, body) = send(&app, Method::GET, &format!("{}?genre=fiksi_ilmiah", uri), ADMIN_KEY, None).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(body["code"], "genre_not_found");
+    }
+
+    #[actix_web::test]
+    async fn book_crud() {
+        let app = app().await;
+        let (user_id, _, book_id) = library(&app).await;
+        let uri = format!("/book/{}/novel/{}", user_id, book_id);
+
+        let (status, body) = send(&app, Method::GET, &uri, ADMIN_KEY, None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["judul"], "Laskar Pelangi");
+        assert_eq!(body["isbn_10"], "9793062797");
+
+        assert_eq!(send(&app, Method::PUT, &uri, ADMIN_KEY, Some(json!({"jumlah_halaman": 530}))).await.0, StatusCode::OK);
+        let (_, body) = send(&app, Method::GET, &format!("{}?return_fields=judul,jumlah_halaman", uri), ADMIN_KEY, None).await;
+        assert_eq!(body, json!({"judul": "Laskar Pelangi", "jumlah_halaman": 530}));
+
+        // Upsert bikin buku kalo belum ada, lalu ganti isinya
+        let new_uri = format!("/book/{}/novel/buku-baru?upsert=true", user_id);
+        let (status, body) = send(&app, Method::PUT, &new_uri, ADMIN_KEY, Some(json!({"judul": "Bumi"}))).await;
+        assert_eq!((status, &body["result"]), (StatusCode::CREATED, &json!("created")));
+        let (status, body) = send(&app, Method::PUT, &new_uri, ADMIN_KEY, Some(json!({"judul": "Bulan"}))).await;
+        assert_eq!((status, &body["result"]), (StatusCode::OK, &json!("updated")));
+
+        assert_eq!(send(&app, Method::DELETE, &uri, ADMIN_KEY, None).await.0, StatusCode::OK);
+        let (status, body) = send(&app, Method::GET, &uri, ADMIN_KEY, None).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(body["code"], "book_not_found");
+    }
+
+    #[actix_web::test]
+    async fn search_books() {
+        let app = app().await;
+        let (user_id, _, book_id) = library(&app).await;
+        let uri = format!("/search/{}", user_id);
+
+        let (status, body) = send(&app, Method::POST, &uri, ADMIN_KEY, Some(json!({"search_term": "laskar"}))).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["total"], 1);
+        assert_eq!(body["data"][0]["_id"], book_id.as_str());
+
+        // Tanpa search_term diurutkan dari judul, tanpa peduli huruf besar dan aksen
+        let (_, body) = send(&app, Method::GET, &format!("{}?return_fields=judul", uri), ADMIN_KEY, None).await;
+        let titles: Vec<&Value> = body["data"].as_array().unwrap().iter().map(|b| &b["_source"]["judul"]).collect();
+        assert_eq!(titles, [&json!("Émile"), &json!("Laskar Pelangi")]);
+
+        let (_, body) = send(&app, Method::GET, &format!("{}?facets=genre,bahasa&book_genre=fiksi", uri), ADMIN_KEY, None).await;
+        assert_eq!(body["total"], 1);
+        assert_eq!(body["facets"]["genre"], json!([{"value": "fiksi", "count": 1}]));
+
+        let (_, body) = send(&app, Method::GET, &format!("{}?jumlah_halaman_max=300", uri), ADMIN_KEY, None).await;
+        assert_eq!(body["data"][0]["_source"]["judul"], "Émile");
+
+        let (status, body) = send(&app, Method::GET, &format!("{}?sort=warna&search_fields=warna", uri), ADMIN_KEY, None).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["code"], "validation_failed");
+    }
+
+    #[actix_web::test]
+    async fn auth_matrix() {
+        let app = app().await;
+        let (library_id, member_key, book_id) = library(&app).await;
+        let (other_id, other_key) = create_user(&app, "anggota", "member").await;
+        let (_, librarian_key) = create_user(&app, "petugas", "librarian").await;
+        let search = format!("/search/{}", library_id);
+        let genre = format!("/genre/{}", library_id);
+
+        // Tanpa key atau key salah
+        assert_eq!(send(&app, Method::GET, &search, "", None).await.0, StatusCode::UNAUTHORIZED);
+        assert_eq!(send(&app, Method::GET, &search, &format!("{}.salah", library_id), None).await.0, StatusCode::UNAUTHORIZED);
+
+        // Member cuma boleh baca library dan akun sendiri
+        assert_eq!(send(&app, Method::GET, &search, &member_key, None).await.0, StatusCode::OK);
+        assert_eq!(send(&app, Method::GET, &search, &other_key, None).await.0, StatusCode::FORBIDDEN);
+        assert_eq!(send(&app, Method::POST, &genre, &member_key, Some(json!({"genre": "puisi"}))).await.0, StatusCode::FORBIDDEN);
+        assert_eq!(send(&app, Method::GET, &format!("/user/{}", other_id), &member_key, None).await.0, StatusCode::FORBIDDEN);
+        assert_eq!(send(&app, Method::PUT, "/user", &member_key, Some(json!({"user_id": other_id, "user_name": "x"}))).await.0, StatusCode::FORBIDDEN);
+        let (status, body) = send(&app, Method::GET, "/users", &member_key, None).await;
+        assert_eq!((status, &body["code"]), (StatusCode::FORBIDDEN, &json!("forbidden")));
+
+        // Librarian boleh baca dan ubah library siapa aja, tapi ga boleh kelola user
+        assert_eq!(send(&app, Method::GET, &search, &librarian_key, None).await.0, StatusCode::OK);
+        assert_eq!(send(&app, Method::POST, &genre, &librarian_key, Some(json!({"genre": "puisi"}))).await.0, StatusCode::CREATED);
+        assert_eq!(send(&app, Method::GET, "/users", &librarian_key, None).await.0, StatusCode::FORBIDDEN);
+        assert_eq!(send(&app, Method::DELETE, &format!("/user/{}", other_id), &librarian_key, None).await.0, StatusCode::FORBIDDEN);
+
+        // Admin boleh semuanya
+        assert_eq!(send(&app, Method::GET, "/users", ADMIN_KEY, None).await.0, StatusCode::OK);
+        assert_eq!(send(&app, Method::DELETE, &format!("{}/puisi", genre), ADMIN_KEY, None).await.0, StatusCode::OK);
+
+        // Member ga boleh pinjamkan buku, librarian boleh
+        let loan = format!("/loan/{}/novel/{}", library_id, book_id);
+        assert_eq!(send(&app, Method::POST, &loan, &other_key, Some(json!({"borrower": other_id}))).await.0, StatusCode::FORBIDDEN);
+        assert_eq!(send(&app, Method::POST, &loan, &librarian_key, Some(json!({"borrower": "peminjam"}))).await.0, StatusCode::CREATED);
+    }
+
+    #[actix_web::test]
+    async fn act_as_patron() {
+        let app = app().await;
+        let (library_id, _, book_id) = library(&app).await;
+        let (other_id, other_key) = create_user(&app, "anggota", "member").await;
+        let (_, librarian_key) = create_user(&app, "petugas", "librarian").await;
+        let loan = format!("/loan/{}/novel/{}", library_id, book_id);
+        let hold = format!("/hold/{}/novel/{}", library_id, book_id);
+        assert_eq!(send(&app, Method::POST, &loan, &librarian_key, Some(json!({"borrower": "peminjam"}))).await.0, StatusCode::CREATED);
+
+        // Member dari library lain boleh antre atas nama sendiri, tapi ga atas nama orang lain
+        let (status, body) = send(&app, Method::POST, &hold, &other_key, Some(json!({"patron": "peminjam"}))).await;
+        assert_eq!((status, &body["code"]), (StatusCode::FORBIDDEN, &json!("forbidden")));
+        let (status, body) = send(&app, Method::POST, &hold, &other_key, Some(json!({}))).await;
+        assert_eq!(status, StatusCode::CREATED);
+        assert_eq!(body["patron"], other_id.as_str());
+
+        // Librarian boleh antre dan batal atas nama siapa aja
+        assert_eq!(send(&app, Method::POST, &hold, &librarian_key, Some(json!({"patron": "tamu"}))).await.0, StatusCode::CREATED);
+        assert_eq!(send(&app, Method::DELETE, &format!("{}?patron=tamu", hold), &other_key, None).await.0, StatusCode::FORBIDDEN);
+        assert_eq!(send(&app, Method::DELETE, &format!("{}?patron=tamu", hold), &librarian_key, None).await.0, StatusCode::OK);
+        assert_eq!(send(&app, Method::DELETE, &hold, &other_key, None).await.0, StatusCode::OK);
+
+        // Perpanjang pinjaman juga cuma atas nama sendiri, pinjamannya punya peminjam lain
+        let renew = format!("{}/renew", loan);
+        assert_eq!(send(&app, Method::POST, &renew, &other_key, None).await.0, StatusCode::FORBIDDEN);
+        assert_eq!(send(&app, Method::POST, &renew, &librarian_key, None).await.0, StatusCode::OK);
+    }
+
+    #[actix_web::test]
+    async fn copies_belong_to_one_genre() {
+        let app = app().await;
+        let (user_id, _) = create_user(&app, "perpus", "member").await;
+        for genre in ["novel", "komik"] {
+            send(&app, Method::POST, &format!("/genre/{}", user_id), ADMIN_KEY, Some(json!({"genre": genre}))).await;
+            let (status, _) = send(&app, Method::PUT, &format!("/book/{}/{}/B1?upsert=true", user_id, genre), ADMIN_KEY, Some(json!({"judul": genre}))).await;
+            assert_eq!(status, StatusCode::CREATED);
+        }
+        let (status, _) = send(&app, Method::POST, &format!("/copy/{}/novel/B1", user_id), ADMIN_KEY, Some(json!({"barcode": "N-1"}))).await;
+        assert_eq!(status, StatusCode::CREATED);
+
+        // Eksemplar novel/B1 ga ikut dihitung untuk komik/B1
+        let (_, body) = send(&app, Method::GET, &format!("/search/{}?copies=true&sort=judul", user_id), ADMIN_KEY, None).await;
+        let copies: Vec<(&Value, &Value)> = body["data"].as_array().unwrap().iter().map(|b| (&b["_source"]["judul"], &b["copies_available"])).collect();
+        assert_eq!(copies, [(&json!("komik"), &json!(0)), (&json!("novel"), &json!(1))]);
+
+        // komik/B1 dipinjam tanpa barcode, lalu ga boleh dapat eksemplar selama masih dipinjam
+        let loan = json!({"borrower": "peminjam"});
+        assert_eq!(send(&app, Method::POST, &format!("/loan/{}/komik/B1", user_id), ADMIN_KEY, Some(loan)).await.0, StatusCode::CREATED);
+        let (status, body) = send(&app, Method::POST, &format!("/copy/{}/komik/B1", user_id), ADMIN_KEY, Some(json!({"barcode": "K-1"}))).await;
+        assert_eq!((status, &body["code"]), (StatusCode::CONFLICT, &json!("book_on_loan")));
+    }
+
+    #[actix_web::test]
+    async fn duplicate_books() {
+        let app = app().await;
+        let (user_id, _, book_id) = library(&app).await;
+        let uri = format!("/book/{}/novel", user_id);
+
+        // Tanpa isbn tetap ketemu dari judul dan penulis buku yang punya isbn, lalu yang kedua di kiriman yang sama juga ditolak
+        let books = json!([
+            {"judul": "laskar  pelangi", "penulis": "ANDREA HIRATA"},
+            {"judul": "Sang Pemimpi", "penulis": "Andrea Hirata", "isbn": "979-3062-92-4"},
+            {"judul": "sang pemimpi", "penulis": "Andrea Hirata"}
+        ]);
+        let (status, body) = send(&app, Method::POST, &uri, ADMIN_KEY, Some(books.clone())).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["duplicates"], json!([{"doc_num": 0, "book_id": book_id, "genre": "novel", "action": "reject"}]));
+        let failed: Vec<(&Value, &Value)> = body["failures"].as_array().unwrap().iter().map(|f| (&f["doc_num"], &f["code"])).collect();
+        assert_eq!(failed, [(&json!(0), &json!(409)), (&json!(2), &json!(409))]);
+
+        // Skip cuma dilaporkan, yang lain sudah ada dari kiriman pertama
+        let (_, body) = send(&app, Method::POST, &format!("{}?on_duplicate=skip", uri), ADMIN_KEY, Some(books)).await;
+        assert_eq!(body["duplicates"].as_array().unwrap().len(), 2);
+        assert_eq!(body["failures"].as_array().unwrap().len(), 1);
+
+        // Upsert mengubah buku yang sudah ada, jumlah buku tetap
+        let update = json!([{"isbn": "9789793062792", "judul": "Laskar Pelangi", "jumlah_halaman": 534}]);
+        let (_, body) = send(&app, Method::POST, &format!("{}?on_duplicate=upsert", uri), ADMIN_KEY, Some(update)).await;
+        assert_eq!(body["duplicates"][0]["action"], "upsert");
+        assert_eq!(body["failures"], json!([]));
+        let (_, book) = send(&app, Method::GET, &format!("{}/{}", uri, book_id), ADMIN_KEY, None).await;
+        assert_eq!(book["jumlah_halaman"], 534);
+        let (_, found) = send(&app, Method::GET, &format!("/search/{}", user_id), ADMIN_KEY, None).await;
+        assert_eq!(found["total"], 3);
+    }
+
+    #[actix_web::test]
+    async fn single_book_reports_every_field() {
+        let app = app().await;
+        let (user_id, _, book_id) = library(&app).await;
+        let invalid = json!({"id": "lain", "judul": 5, "jumlah_halaman": -1, "warna": "biru"});
+
+        for uri in [format!("/book/{}/novel/{}", user_id, book_id), format!("/book/{}/novel/baru?upsert=true", user_id)] {
+            let (status, body) = send(&app, Method::PUT, &uri, ADMIN_KEY, Some(invalid.clone())).await;
+            assert_eq!(status, StatusCode::BAD_REQUEST);
+            assert_eq!(body["code"], "validation_failed");
+            let mut fields: Vec<&str> = body["fields"].as_array().unwrap().iter().filter_map(|f| f["field"].as_str()).collect();
+            fields.sort();
+            assert_eq!(fields, ["id", "judul", "jumlah_halaman", "warna"]);
+        }
+    }
+
+    /// Id point in time di dalam cursor
+    fn cursor_pit(cursor: &Value) -> String {
+        use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+        let raw: Value = serde_json::from_slice(&URL_SAFE_NO_PAD.decode(cursor.as_str().unwrap()).unwrap()).unwrap();
+        raw["pit"].as_str().unwrap().to_string()
+    }
+
+    #[actix_web::test]
+    async fn search_with_cursor() {
+        let store: Arc<dyn LibraryStore> = Arc::new(MemoryStore::new());
+        let app = app_with(store.clone()).await;
+        let (user_id, _, _) = library(&app).await;
+        let more = json!([{"judul": "Bumi"}, {"judul": "Cantik Itu Luka"}, {"judul": "Dilan"}]);
+        send(&app, Method::POST, &format!("/book/{}/novel", user_id), ADMIN_KEY, Some(more)).await;
+        let uri = format!("/search/{}?return_fields=judul&count=2", user_id);
+        let titles = |body: &Value| body["data"].as_array().unwrap().iter().map(|b| b["_source"]["judul"].as_str().unwrap().to_string()).collect::<Vec<String>>();
+
+        // Halaman pertama buka point in time, buku yang masuk setelahnya ga ikut
+        let (status, first) = send(&app, Method::GET, &format!("{}&use_cursor=true", uri), ADMIN_KEY, None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(titles(&first), ["Bumi", "Cantik Itu Luka"]);
+        send(&app, Method::POST, &format!("/book/{}/novel", user_id), ADMIN_KEY, Some(json!([{"judul": "Aroma Karsa"}]))).await;
+
+        let (_, second) = send(&app, Method::GET, &format!("{}&cursor={}", uri, first["next_cursor"].as_str().unwrap()), ADMIN_KEY, None).await;
+        assert_eq!(titles(&second), ["Dilan", "Émile"]);
+        let (_, last) = send(&app, Method::GET, &format!("{}&cursor={}", uri, second["next_cursor"].as_str().unwrap()), ADMIN_KEY, None).await;
+        assert_eq!(titles(&last), ["Laskar Pelangi"]);
+        assert_eq!(last["next_cursor"], Value::Null);
+
+        // Halaman terakhir menutup point in time, cursor sebelumnya jadi kedaluwarsa
+        let (status, body) = send(&app, Method::GET, &format!("{}&cursor={}", uri, second["next_cursor"].as_str().unwrap()), ADMIN_KEY, None).await;
+        assert_eq!((status, &body["code"]), (StatusCode::GONE, &json!("cursor_expired")));
+
+        // Point in time yang habis waktunya juga 410
+        let (_, page) = send(&app, Method::GET, &format!("{}&use_cursor=true", uri), ADMIN_KEY, None).await;
+        store.close_point_in_time(&cursor_pit(&page["next_cursor"])).await.unwrap();
+        let (status, _) = send(&app, Method::GET, &format!("{}&cursor={}", uri, page["next_cursor"].as_str().unwrap()), ADMIN_KEY, None).await;
+        assert_eq!(status, StatusCode::GONE);
+
+        // Cursor cuma untuk pencarian yang sama, ga bisa bareng from, dan harus cursor yang benar
+        let (_, page) = send(&app, Method::GET, &format!("{}&use_cursor=true", uri), ADMIN_KEY, None).await;
+        let cursor = page["next_cursor"].as_str().unwrap();
+        for (params, field) in [(format!("&sort=penulis&cursor={}", cursor), "cursor"), (format!("&from=2&cursor={}", cursor), "from"), ("&cursor=bukan-cursor".to_string(), "cursor")] {
+            let (status, body) = send(&app, Method::GET, &format!("{}{}", uri, params), ADMIN_KEY, None).await;
+            assert_eq!(status, StatusCode::BAD_REQUEST);
+            assert_eq!(body["fields"][0]["field"], field);
+        }
+    }
+
+    #[actix_web::test]
+    async fn deep_pages_need_a_cursor() {
+        let app = app().await;
+        let (user_id, _, _) = library(&app).await;
+
+        let (status, _) = send(&app, Method::GET, &format!("/search/{}?from=9980&count=20", user_id), ADMIN_KEY, None).await;
+        assert_eq!(status, StatusCode::OK);
+        let (status, body) = send(&app, Method::GET, &format!("/search/{}?from=9990&count=20", user_id), ADMIN_KEY, None).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["code"], "validation_failed");
+        assert!(body["fields"][0]["message"].as_str().unwrap().contains("use_cursor"));
+    }
+
+    #[actix_web::test]
+    async fn emptied_isbn_is_no_longer_found() {
+        let app = app().await;
+        let (user_id, _, book_id) = library(&app).await;
+        let by_isbn = format!("/isbn/{}/979-3062-79-7", user_id);
+        assert_eq!(send(&app, Method::GET, &by_isbn, ADMIN_KEY, None).await.0, StatusCode::OK);
+
+        let (status, _) = send(&app, Method::PUT, &format!("/book/{}/novel/{}", user_id, book_id), ADMIN_KEY, Some(json!({"isbn": ""}))).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(send(&app, Method::GET, &by_isbn, ADMIN_KEY, None).await.0, StatusCode::NOT_FOUND);
+        let (_, book) = send(&app, Method::GET, &format!("/book/{}/novel/{}", user_id, book_id), ADMIN_KEY, None).await;
+        assert_eq!((&book["isbn"], &book["isbn_10"], &book["isbn_raw"]), (&Value::Null, &Value::Null, &Value::Null));
+    }
+
+    #[actix_web::test]
+    async fn api_key_headers() {
+        let app = app().await;
+        let call = |headers: Vec<(&'static str, String)>| {
+            let req = headers.into_iter().fold(TestRequest::get().uri("/users"), |r, h| r.insert_header(h)).to_request();
+            let response = app.call(req);
+            async move { response.await.map(|r| r.status()).unwrap_or_else(|e| e.error_response().status()) }
+        };
+
+        // Authorization lain dari proxy ga menghalangi X-API-Key
+        assert_eq!(call(vec![("Authorization", "Basic dXNlcjpwYXNz".to_string()), ("X-API-Key", ADMIN_KEY.to_string())]).await, StatusCode::OK);
+        assert_eq!(call(vec![("Authorization", format!("Bearer {}", ADMIN_KEY))]).await, StatusCode::OK);
+        assert_eq!(call(vec![("Authorization", "Bearer salah".to_string()), ("X-API-Key", ADMIN_KEY.to_string())]).await, StatusCode::UNAUTHORIZED);
+        assert_eq!(call(vec![("Authorization", "Basic dXNlcjpwYXNz".to_string())]).await, StatusCode::UNAUTHORIZED);
+    }
+}
@@ -0,0 +1,414 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use actix_web::http::StatusCode;
+use serde_json::{json, Value};
+use crate::{config::Config, store::LibraryStore, libs::*};
+
+/// Versi mapping indeks genre, naikkan setiap mappingnya diubah lalu jalankan `library_app migrate`.
+/// Indeks lama yang belum punya versi dianggap versi 0
+pub const BOOK_MAPPING_VERSION: i64 = 5;
+
+/// Awalan semua indeks genre, supaya template ga kena indeks lain yang namanya pakai titik
+const BOOK_INDEX_PREFIX: &str = "books-";
+
+/// Pola nama indeks genre, books-user_id.genre
+const BOOK_INDEX_PATTERN: &str = "books-*";
+
+/// Nama indeks satu genre milik user
+pub fn book_index(user_id: &str, genre: &str) -> String {
+    format!("{}{}.{}", BOOK_INDEX_PREFIX, user_id.to_lowercase(), genre)
+}
+
+/// Awalan indeks semua genre milik user, buat cari di semua genrenya
+pub fn user_books_prefix(user_id: &str) -> String {
+    format!("{}{}.", BOOK_INDEX_PREFIX, user_id.to_lowercase())
+}
+
+/// Nama indeks genre sebelum pakai awalan, user_id.genre
+fn legacy_index(index: &str) -> &str {
+    index.strip_prefix(BOOK_INDEX_PREFIX).unwrap_or(index)
+}
+
+/// Nama indeks sementara waktu migrasi, ikut awalan supaya dapat template tapi ga kena pencarian user
+fn migrate_temp(index: &str) -> String {
+    format!("{}migrate-v{}-{}", BOOK_INDEX_PREFIX, BOOK_MAPPING_VERSION, legacy_index(index))
+}
+
+/// Banyaknya user per halaman waktu mencari semua indeks genre
+const USER_PAGE_SIZE: i64 = 1_000;
+
+/// Sudah didaftarkan ke elastic atau belum, supaya ga dikirim setiap bikin genre
+static TEMPLATE_READY: AtomicBool = AtomicBool::new(false);
+
+/// Mapping indeks genre. Field di luar daftar tetap disimpan tapi ga diindeks, jadi tipenya ga berubah-ubah
+/// tergantung dokumen pertama yang masuk
+fn book_mappings() -> Value {
+    // keyword untuk facet dan filter yang sama persis, sort untuk urutan tanpa peduli huruf besar
+    let text_with_keyword = json!({
+        "type": "text",
+        "analyzer": "book_text",
+        "fields": {
+            "keyword": {"type": "keyword", "ignore_above": 256},
+            "sort": {"type": "keyword", "normalizer": "book_sort", "ignore_above": 256}
+        }
+    });
+    // Awalan tiap kata ikut diindeks untuk saran waktu mengetik
+    let suggest = json!({"type": "text", "analyzer": "book_autocomplete", "search_analyzer": "book_text"});
+    let mut penulis = text_with_keyword.clone();
+    penulis["fields"]["suggest"] = suggest.clone();
+    json!({
+        "_meta": {"mapping_version": BOOK_MAPPING_VERSION},
+        "dynamic": "false",
+        "properties": {
+            "isbn": {
+                "type": "keyword",
+                "fields": {
+                    "text": {"type": "text"}
+                }
+            },
+            "isbn_10": {"type": "keyword"},
+            "isbn_raw": {"type": "keyword"},
+            // Judul juga dianalisis per bahasa, yang dipakai waktu cari dipilih dari field bahasa
+            "judul": {
+                "type": "text",
+                "analyzer": "book_text",
+                "fields": {
+                    "keyword": {"type": "keyword", "ignore_above": 256},
+                    "sort": {"type": "keyword", "normalizer": "book_sort", "ignore_above": 256},
+                    "suggest": suggest,
+                    "id": {"type": "text", "analyzer": "book_id"},
+                    "en": {"type": "text", "analyzer": "book_en"}
+                }
+            },
+            "penulis": penulis,
+            "penerbit": text_with_keyword,
+            "genre": {"type": "keyword"},
+            "bahasa": {"type": "keyword"},
+            "jumlah_halaman": {"type": "integer", "ignore_malformed": true},
+            "tanggal_terbit": {
+                "type": "date",
+                "format": "dd-MM-yyyy",
+                "ignore_malformed": true
+            }
+        }
+    })
+}
+
+/// Index template semua indeks genre, setting, analyzer dan mapping cuma diatur di sini
+fn book_template_body(cfg: &Config) -> Value {
+    json!({
+        "index_patterns": [BOOK_INDEX_PATTERN],
+        "priority": 10,
+        "version": BOOK_MAPPING_VERSION,
+        "template": {
+            "settings": {
+                "number_of_shards": cfg.library.book_shards,
+                "number_of_replicas": cfg.library.book_replicas,
+                "analysis": {
+                    "char_filter": {
+                        // Kata ulang seperti buku-buku jadi buku
+                        "reduplication": {
+                            "type": "pattern_replace",
+                            "pattern": "(?i)\\b(\\p{L}+)-\\1\\b",
+                            "replacement": "$1"
+                        }
+                    },
+                    "filter": {
+                        "indonesian_stop": {"type": "stop", "stopwords": "_indonesian_"},
+                        "indonesian_stemmer": {"type": "stemmer", "language": "indonesian"},
+                        "english_stop": {"type": "stop", "stopwords": "_english_"},
+                        "english_stemmer": {"type": "stemmer", "language": "english"},
+                        "english_possessive_stemmer": {"type": "stemmer", "language": "possessive_english"},
+                        "autocomplete_prefix": {"type": "edge_ngram", "min_gram": 1, "max_gram": 20}
+                    },
+                    "normalizer": {
+                        "book_sort": {
+                            "type": "custom",
+                            "filter": ["lowercase", "asciifolding"]
+                        }
+                    },
+                    "analyzer": {
+                        "book_text": {
+                            "type": "custom",
+                            "tokenizer": "standard",
+                            "filter": ["lowercase", "asciifolding"]
+                        },
+                        "book_autocomplete": {
+                            "type": "custom",
+                            "tokenizer": "standard",
+                            "filter": ["lowercase", "asciifolding", "autocomplete_prefix"]
+                        },
+                        "book_id": {
+                            "type": "custom",
+                            "char_filter": ["reduplication"],
+                            "tokenizer": "standard",
+                            "filter": ["lowercase", "asciifolding", "indonesian_stop", "indonesian_stemmer"]
+                        },
+                        "book_en": {
+                            "type": "custom",
+                            "tokenizer": "standard",
+                            "filter": ["english_possessive_stemmer", "lowercase", "asciifolding", "english_stop", "english_stemmer"]
+                        }
+                    }
+                }
+            },
+            "mappings": book_mappings()
+        }
+    })
+}
+
+/// Daftarkan index template genre ke elastic, dipanggil waktu server mulai
+pub async fn register_book_template(cfg: &Config, db: &dyn LibraryStore) -> Result<(), Errors> {
+    let response = db.put_index_template(&cfg.library.book_template, &book_template_body(cfg)).await?;
+    if !response.status_code().is_success() {
+        return Err(store_error(&response, Errors::Unknown));
+    }
+    TEMPLATE_READY.store(true, Ordering::SeqCst);
+    Ok(())
+}
+
+/// Bikin indeks genre, setting dan mappingnya dari template.
+/// Kalo waktu server mulai elasticnya mati, templatenya didaftarkan di sini
+pub async fn create_book_index(index: &str, cfg: &Config, db: &dyn LibraryStore) -> Result<(), Errors> {
+    if !TEMPLATE_READY.load(Ordering::SeqCst) {
+        register_book_template(cfg, db).await?;
+    }
+    create_index_if_missing(index, &json!({}), db).await
+}
+
+/// Mapping indeks user
+pub fn user_index_body() -> Value {
+    json!({
+        "mappings": {
+            "properties": {
+                "name": {"type": "text"},
+                "role": {"type": "keyword"},
+                "api_key_hash": {"type": "keyword"},
+                "genres": {"type": "keyword"}
+            }
+        }
+    })
+}
+
+/// Versi mapping yang tercatat di indeks, None kalo indeksnya ga ada
+async fn mapping_version(index: &str, db: &dyn LibraryStore) -> Result<Option<i64>, Errors> {
+    let response = db.get_mapping(index).await?;
+    match response.status_code() {
+        s if s.is_success() => (),
+        StatusCode::NOT_FOUND => return Ok(None),
+        _ => return Err(store_error(&response, Errors::Unknown))
+    }
+    Ok(Some(response.body[index]["mappings"]["_meta"]["mapping_version"].as_i64().unwrap_or(0)))
+}
+
+/// Jumlah dokumen di satu indeks
+async fn count_documents(index: &str, db: &dyn LibraryStore) -> Result<u64, Errors> {
+    let response = db.search(index, &json!({"track_total_hits": true, "query": {"match_all": {}}}), None, Some(0)).await?;
+    if !response.status_code().is_success() {
+        return Err(store_error(&response, Errors::Unknown));
+    }
+    Ok(response.body["hits"]["total"]["value"].as_u64().unwrap_or_default())
+}
+
+/// Salin isi indeks lalu pastikan semuanya masuk, kalo ada yang gagal berhenti.
+/// Tujuan boleh sudah berisi, dokumen yang sudah ada di sana ga ditimpa
+async fn copy_index(source: &str, dest: &str, db: &dyn LibraryStore) -> Result<u64, Errors> {
+    let response = db.reindex(source, dest).await?;
+    if !response.status_code().is_success() {
+        return Err(store_error(&response, Errors::Unknown));
+    }
+    if let Some(failure) = response.body["failures"].as_array().and_then(|f| f.first()) {
+        let reason = failure["cause"]["reason"].as_str().unwrap_or_default();
+        return Err(Errors::Conflict(format!("Cannot copy {} to {}: {}", source, dest, reason)));
+    }
+
+    let (expected, copied) = (count_documents(source, db).await?, count_documents(dest, db).await?);
+    if copied < expected {
+        return Err(Errors::Conflict(format!("Copied {} of {} documents from {} to {}", copied, expected, source, dest)));
+    }
+    Ok(copied)
+}
+
+/// Semua indeks genre milik semua user
+async fn genre_indices(cfg: &Config, db: &dyn LibraryStore) -> Result<Vec<String>, Errors> {
+    // Lewat point in time supaya semua user terbaca, bukan cuma 10000 pertama
+    let response = db.open_point_in_time(&cfg.library.user_index, "5m").await?;
+    match response.status_code() {
+        s if s.is_success() => (),
+        StatusCode::NOT_FOUND => return Ok(vec![]),
+        _ => return Err(store_error(&response, Errors::Unknown))
+    }
+    let mut pit = response.body["id"].as_str().unwrap_or_default().to_string();
+
+    let mut indices = vec![];
+    let mut body = json!({"_source": ["genres"], "query": {"match_all": {}}, "sort": ["_shard_doc"]});
+    loop {
+        body["pit"] = json!({"id": pit, "keep_alive": "5m"});
+        let response = db.search_point_in_time(&body, Some(USER_PAGE_SIZE)).await?;
+        if !response.status_code().is_success() {
+            db.close_point_in_time(&pit).await?;
+            return Err(store_error(&response, Errors::Unknown));
+        }
+        pit = response.body["pit_id"].as_str().map(str::to_string).unwrap_or(pit);
+
+        let hits = response.body["hits"]["hits"].as_array().cloned().unwrap_or_default();
+        for hit in &hits {
+            let user_id = hit["_id"].as_str().unwrap_or_default();
+            for genre in hit["_source"]["genres"].as_array().into_iter().flatten().filter_map(Value::as_str) {
+                indices.push(book_index(user_id, genre));
+            }
+        }
+        match hits.last() {
+            Some(last) if hits.len() as i64 >= USER_PAGE_SIZE => body["search_after"] = last["sort"].clone(),
+            _ => break
+        }
+    }
+    db.close_point_in_time(&pit).await?;
+    indices.sort();
+    Ok(indices)
+}
+
+/// Pindahkan satu indeks ke mapping terbaru lewat indeks sementara:
+/// salin ke indeks sementara, hapus yang lama, buat lagi dengan mapping baru, salin balik.
+/// Kalo berhenti di tengah, jalankan lagi dan dilanjutkan dari indeks sementaranya
+async fn migrate_index(index: &str, cfg: &Config, db: &dyn LibraryStore) -> Result<u64, Errors> {
+    let temp = migrate_temp(index);
+    let current = mapping_version(index, db).await?;
+    let temp_exists = mapping_version(&temp, db).await?.is_some();
+
+    // Indeks lama masih utuh, salinan sementara sisa percobaan sebelumnya dibuang
+    if current.map(|v| v < BOOK_MAPPING_VERSION).unwrap_or(false) {
+        if temp_exists {
+            db.delete_single_index(temp.clone()).await?;
+        }
+        create_book_index(&temp, cfg, db).await?;
+        copy_index(index, &temp, db).await?;
+
+        let response = db.delete_single_index(index.to_string()).await?;
+        if !response.status_code().is_success() {
+            return Err(store_error(&response, Errors::Unknown));
+        }
+    } else if !temp_exists {
+        return Err(Errors::Conflict(format!("Cannot find {} or {}", index, temp)));
+    }
+
+    // Dari sini isinya ada di indeks sementara
+    create_book_index(index, cfg, db).await?;
+    let copied = copy_index(&temp, index, db).await?;
+    db.delete_single_index(temp).await?;
+    Ok(copied)
+}
+
+/// Pindahkan indeks dengan nama lama ke nama berawalan, indeks barunya langsung pakai mapping terbaru.
+/// Indeks baru bisa sudah berisi buku yang masuk setelah server baru jalan, jadi ga dihapus, isi lama ditambahkan
+/// tanpa menimpa buku yang id-nya sama. Indeks lama baru dihapus setelah isinya tersalin semua, jadi kalo berhenti
+/// di tengah tinggal jalankan lagi
+async fn rename_legacy_index(index: &str, cfg: &Config, db: &dyn LibraryStore) -> Result<u64, Errors> {
+    create_book_index(index, cfg, db).await?;
+    let copied = copy_index(legacy_index(index), index, db).await?;
+
+    let response = db.delete_single_index(legacy_index(index).to_string()).await?;
+    if !response.status_code().is_success() {
+        return Err(store_error(&response, Errors::Unknown));
+    }
+    Ok(copied)
+}
+
+/// Perintah `library_app migrate`, pindahkan semua indeks genre yang mappingnya masih lama.
+/// Jalankan waktu server mati, tulisan yang masuk selama pindah bisa hilang
+pub async fn migrate(dry_run: bool, cfg: &Config, db: &dyn LibraryStore) -> Result<(), Errors> {
+    check_server(db).await?;
+    if !dry_run {
+        register_book_template(cfg, db).await?;
+    }
+    let mut failed = 0;
+    for index in genre_indices(cfg, db).await? {
+        // Indeks dari sebelum ada awalan dipindah dulu ke nama barunya
+        if mapping_version(legacy_index(&index), db).await?.is_some() {
+            if dry_run {
+                println!("{}: would move from {}", index, legacy_index(&index));
+                continue;
+            }
+            match rename_legacy_index(&index, cfg, db).await {
+                Ok(count) => println!("{}: moved books from {}, it has {} books now", index, legacy_index(&index), count),
+                Err(e) => {
+                    failed += 1;
+                    eprintln!("{}: move from {} failed, run the command again to resume: {}", index, legacy_index(&index), e);
+                }
+            }
+            continue;
+        }
+
+        let temp_exists = mapping_version(&migrate_temp(&index), db).await?.is_some();
+        let version = match mapping_version(&index, db).await? {
+            Some(v) if v >= BOOK_MAPPING_VERSION && !temp_exists => {
+                println!("{}: already at mapping version {}", index, v);
+                continue;
+            },
+            Some(v) => v,
+            None if temp_exists => -1,
+            None => {
+                println!("{}: index is missing, skipped", index);
+                continue;
+            }
+        };
+
+        if dry_run {
+            println!("{}: would migrate from mapping version {} to {}", index, version.max(0), BOOK_MAPPING_VERSION);
+            continue;
+        }
+        match migrate_index(&index, cfg, db).await {
+            Ok(count) => println!("{}: migrated {} books to mapping version {}", index, count, BOOK_MAPPING_VERSION),
+            Err(e) => {
+                failed += 1;
+                eprintln!("{}: migration failed, run the command again to resume: {}", index, e);
+            }
+        }
+    }
+
+    if failed > 0 {
+        return Err(Errors::Conflict(format!("{} indices failed to migrate", failed)));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::memory::MemoryStore;
+    use super::*;
+
+    #[actix_web::test]
+    async fn migrate_keeps_books_already_in_the_prefixed_index() {
+        let cfg = Config::default();
+        let db = MemoryStore::new();
+        register_book_template(&cfg, &db).await.unwrap();
+        db.create_document(&cfg.library.user_index, "U1", &json!({"genres": ["novel"]})).await.unwrap();
+        db.put_document("u1.novel", "lama", &json!({"judul": "Sebelum deploy"})).await.unwrap();
+        db.put_document("u1.novel", "sama", &json!({"judul": "Versi lama"})).await.unwrap();
+        db.put_document("books-u1.novel", "baru", &json!({"judul": "Setelah deploy"})).await.unwrap();
+        db.put_document("books-u1.novel", "sama", &json!({"judul": "Versi baru"})).await.unwrap();
+
+        // Dijalankan dua kali tetap aman
+        migrate(false, &cfg, &db).await.unwrap();
+        migrate(false, &cfg, &db).await.unwrap();
+
+        assert_eq!(mapping_version("u1.novel", &db).await.unwrap(), None);
+        assert_eq!(mapping_version("books-u1.novel", &db).await.unwrap(), Some(BOOK_MAPPING_VERSION));
+        assert_eq!(count_documents("books-u1.novel", &db).await.unwrap(), 3);
+        let same = db.get_single_document("books-u1.novel", "sama", None).await.unwrap();
+        assert_eq!(same.body["judul"], "Versi baru");
+    }
+
+    #[actix_web::test]
+    async fn genre_indices_reads_every_user() {
+        let cfg = Config::default();
+        let db = MemoryStore::new();
+        let users = USER_PAGE_SIZE * 2 + 1;
+        for n in 0..users {
+            db.create_document(&cfg.library.user_index, &format!("U{}", n), &json!({"genres": ["novel"]})).await.unwrap();
+        }
+
+        let indices = genre_indices(&cfg, &db).await.unwrap();
+        assert_eq!(indices.len() as i64, users);
+        assert!(indices.contains(&book_index("U2000", "novel")));
+    }
+}
@@ -0,0 +1,949 @@
+use std::{cmp::Ordering, collections::BTreeMap, sync::{RwLock, atomic::{AtomicU64, Ordering as AtomicOrdering}}, time::{Duration, Instant}};
+use actix_web::http::StatusCode;
+use async_trait::async_trait;
+use elasticsearch::Error;
+use serde_json::{json, Map, Value};
+use crate::store::{LibraryStore, StoreResponse};
+
+/// Satu indeks di memori
+#[derive(Default)]
+struct MemoryIndex {
+    mappings: Value,
+    docs: BTreeMap<String, Value>
+}
+
+/// Penyimpanan di memori, supaya API bisa jalan tanpa elastic (untuk tes dan laptop)
+#[derive(Default)]
+pub struct MemoryStore {
+    indices: RwLock<BTreeMap<String, MemoryIndex>>,
+    templates: RwLock<BTreeMap<String, Value>>,
+    /// Point in time yang masih dibuka, isinya batas waktu dan salinan indeksnya
+    pits: RwLock<BTreeMap<String, (Instant, Snapshot)>>,
+    next_id: AtomicU64
+}
+
+/// Salinan isi beberapa indeks, nama indeks -> id -> dokumen
+type Snapshot = BTreeMap<String, BTreeMap<String, Value>>;
+
+/// Dokumen yang ikut diagregasi beserta nama indeksnya
+type AggDocs<'a> = Vec<(&'a str, &'a Value)>;
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Bikin id dokumen baru, urutannya sama dengan urutan masuk
+    fn generate_id(&self) -> String {
+        format!("mem{:017}", self.next_id.fetch_add(1, AtomicOrdering::SeqCst))
+    }
+
+    /// Mapping dari template yang cocok dengan nama indeks, prioritas tertinggi yang dipakai
+    fn template_mappings(&self, index: &str) -> Value {
+        let templates = self.templates.read().unwrap();
+        templates.values()
+            .filter(|t| t["index_patterns"].as_array().into_iter().flatten().filter_map(Value::as_str).any(|p| wildcard_match(p, index)))
+            .max_by_key(|t| t["priority"].as_i64().unwrap_or(0))
+            .map(|t| t["template"]["mappings"].clone())
+            .unwrap_or(Value::Null)
+    }
+
+    /// Ambil indeks, kalo belum ada dibuat otomatis pakai template seperti elastic
+    fn index_or_create<'a>(&self, indices: &'a mut BTreeMap<String, MemoryIndex>, index: &str) -> &'a mut MemoryIndex {
+        indices.entry(index.to_string()).or_insert_with(|| MemoryIndex {
+            mappings: self.template_mappings(index),
+            docs: BTreeMap::new()
+        })
+    }
+}
+
+/// Bentuk eror yang sama seperti yang dikirim elastic
+fn error(status: StatusCode, kind: &str, reason: String) -> StoreResponse {
+    StoreResponse::new(status, json!({
+        "error": {
+            "root_cause": [{"type": kind, "reason": reason}],
+            "type": kind,
+            "reason": reason
+        },
+        "status": status.as_u16()
+    }))
+}
+
+fn index_not_found(index: &str) -> StoreResponse {
+    error(StatusCode::NOT_FOUND, "index_not_found_exception", format!("no such index [{}]", index))
+}
+
+/// Cocokin teks dengan pola yang boleh pakai wildcard *
+fn wildcard_match(pattern: &str, text: &str) -> bool {
+    let parts: Vec<&str> = pattern.split('*').collect();
+    if parts.len() == 1 {
+        return pattern == text;
+    }
+
+    // Bagian pertama harus di awal, bagian terakhir harus di akhir
+    let (first, last) = (parts[0], parts[parts.len() - 1]);
+    if !text.starts_with(first) || text.len() < first.len() + last.len() || !text.ends_with(last) {
+        return false;
+    }
+
+    // Bagian tengah dicari berurutan
+    let mut rest = &text[first.len()..text.len() - last.len()];
+    for part in &parts[1..parts.len() - 1] {
+        match rest.find(part) {
+            Some(pos) => rest = &rest[pos + part.len()..],
+            None => return false
+        }
+    }
+    true
+}
+
+/// Cari nama indeks yang cocok dengan ekspresi indeks, kalo indeks konkretnya ga ada dikembalikan namanya
+fn resolve(indices: &BTreeMap<String, MemoryIndex>, expression: &str) -> Result<Vec<String>, String> {
+    let mut names: Vec<String> = vec![];
+    for part in expression.split(',').map(str::trim) {
+        let part = if part.is_empty() || part == "_all" { "*" } else { part };
+        if part.contains('*') {
+            for name in indices.keys().filter(|n| wildcard_match(part, n)) {
+                if !names.contains(name) {
+                    names.push(name.clone());
+                }
+            }
+        } else if indices.contains_key(part) {
+            if !names.iter().any(|n| n == part) {
+                names.push(part.to_string());
+            }
+        } else {
+            return Err(part.to_string());
+        }
+    }
+    Ok(names)
+}
+
+/// Ambil semua nilai di suatu path field, array diratakan.
+/// Kalo ga ketemu, coba tanpa bagian terakhir supaya sub-field seperti judul.keyword tetap kena
+fn field_values<'a>(doc: &'a Value, path: &str) -> Vec<&'a Value> {
+    let mut values = vec![];
+    collect_values(doc, &path.split('.').collect::<Vec<_>>(), &mut values);
+    if values.is_empty() {
+        if let Some((parent, _)) = path.rsplit_once('.') {
+            return field_values(doc, parent).into_iter().filter(|v| !v.is_object()).collect();
+        }
+    }
+    values
+}
+
+fn collect_values<'a>(value: &'a Value, path: &[&str], out: &mut Vec<&'a Value>) {
+    match value {
+        Value::Array(items) => items.iter().for_each(|i| collect_values(i, path, out)),
+        Value::Null => (),
+        _ if path.is_empty() => out.push(value),
+        Value::Object(map) => if let Some(v) = map.get(path[0]) { collect_values(v, &path[1..], out) },
+        _ => ()
+    }
+}
+
+/// Ambil semua nilai skalar di dokumen beserta path-nya
+fn leaf_values<'a>(value: &'a Value, prefix: String, out: &mut Vec<(String, &'a Value)>) {
+    match value {
+        Value::Object(map) => for (k, v) in map {
+            let path = if prefix.is_empty() { k.clone() } else { format!("{}.{}", prefix, k) };
+            leaf_values(v, path, out);
+        },
+        Value::Array(items) => items.iter().for_each(|i| leaf_values(i, prefix.clone(), out)),
+        Value::Null => (),
+        _ => out.push((prefix, value))
+    }
+}
+
+fn value_text(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        x => x.to_string()
+    }
+}
+
+/// Pecah teks jadi token huruf kecil, mirip analyzer standar
+fn tokens(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|t| !t.is_empty())
+        .map(str::to_lowercase)
+        .collect()
+}
+
+/// Ubah tanggal dd-MM-yyyy jadi yyyy-MM-dd supaya bisa dibandingin, format lain dibiarkan
+fn date_key(text: &str) -> String {
+    let parts: Vec<&str> = text.split('-').collect();
+    if parts.len() == 3 && parts[0].len() == 2 && parts[1].len() == 2 && parts[2].len() == 4 {
+        return format!("{}-{}-{}", parts[2], parts[1], parts[0]);
+    }
+    text.to_string()
+}
+
+/// Bandingin dua nilai skalar, angka sebagai angka dan teks sebagai tanggal atau teks biasa
+fn compare_values(a: &Value, b: &Value) -> Option<Ordering> {
+    match (a.as_f64(), b.as_f64()) {
+        (Some(x), Some(y)) => x.partial_cmp(&y),
+        _ => {
+            let (x, y) = (value_text(a), value_text(b));
+            match (x.parse::<f64>(), y.parse::<f64>()) {
+                (Ok(x), Ok(y)) => x.partial_cmp(&y),
+                _ => Some(date_key(&x).cmp(&date_key(&y)))
+            }
+        }
+    }
+}
+
+fn values_equal(a: &Value, b: &Value) -> bool {
+    a == b || compare_values(a, b) == Some(Ordering::Equal)
+}
+
+/// Ambil daftar field untuk urutan dari "sort", hasilnya (field, turun)
+fn sort_keys(sort: &Value) -> Vec<(String, bool)> {
+    let items = match sort {
+        Value::Array(a) => a.clone(),
+        Value::Null => vec![],
+        x => vec![x.clone()]
+    };
+    items.iter().filter_map(|item| match item {
+        Value::String(field) => Some((field.clone(), field == "_score")),
+        Value::Object(o) => o.iter().next().map(|(field, spec)| {
+            let order = spec.as_str().or(spec["order"].as_str()).unwrap_or(if field == "_score" { "desc" } else { "asc" });
+            (field.clone(), order.eq_ignore_ascii_case("desc"))
+        }),
+        _ => None
+    }).collect()
+}
+
+/// Huruf kecil tanpa aksen, sama seperti filter lowercase dan asciifolding
+fn sort_text(text: &str) -> String {
+    text.to_lowercase().chars().map(|c| match c {
+        'à' | 'á' | 'â' | 'ã' | 'ä' | 'å' | 'ā' => 'a',
+        'ç' | 'ć' | 'č' => 'c',
+        'è' | 'é' | 'ê' | 'ë' | 'ē' => 'e',
+        'ì' | 'í' | 'î' | 'ï' | 'ī' => 'i',
+        'ñ' | 'ń' => 'n',
+        'ò' | 'ó' | 'ô' | 'õ' | 'ö' | 'ø' | 'ō' => 'o',
+        'ś' | 'š' => 's',
+        'ù' | 'ú' | 'û' | 'ü' | 'ū' => 'u',
+        'ý' | 'ÿ' => 'y',
+        'ź' | 'ż' | 'ž' => 'z',
+        c => c
+    }).collect()
+}
+
+/// Bandingin dua hasil sesuai field urutan, dokumen yang tidak punya field ditaruh paling belakang
+fn compare_by_keys(keys: &[(String, bool)], a: (f64, &Value), b: (f64, &Value)) -> Ordering {
+    for (field, descending) in keys {
+        // Sub-field sort di elastic pakai normalizer book_sort, jadi di sini juga disamakan
+        let value = |doc: &Value| field_values(doc, field).first().map(|v| match field.ends_with(".sort") {
+            true => json!(sort_text(&value_text(v))),
+            false => (*v).clone()
+        });
+        let (x, y) = match field.as_str() {
+            "_score" => (Some(json!(a.0)), Some(json!(b.0))),
+            _ => (value(a.1), value(b.1))
+        };
+        let ordering = match (x, y) {
+            (Some(x), Some(y)) => {
+                let o = compare_values(&x, &y).unwrap_or(Ordering::Equal);
+                if *descending { o.reverse() } else { o }
+            },
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (None, None) => Ordering::Equal
+        };
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+    Ordering::Equal
+}
+
+/// Ambil field dan isi dari query satu field seperti {"judul": "x"} atau {"judul": {"value": "x"}}
+fn single_field<'a>(query: &'a Value, inner: &str) -> Option<(&'a String, &'a Value)> {
+    let (field, spec) = query.as_object()?.iter().next()?;
+    Some((field, spec.get(inner).unwrap_or(spec)))
+}
+
+/// Cari nilai field yang disebut di daftar fields (boleh wildcard dan boost), kosong berarti semua field
+fn searchable_values<'a>(doc: &'a Value, fields: Option<&Value>) -> Vec<&'a Value> {
+    let mut leaves = vec![];
+    leaf_values(doc, String::new(), &mut leaves);
+
+    let patterns: Vec<String> = fields
+        .and_then(Value::as_array)
+        .map(|f| f.iter().filter_map(Value::as_str).map(|p| p.split('^').next().unwrap_or(p).to_string()).collect())
+        .unwrap_or_default();
+    if patterns.is_empty() || patterns.iter().any(|p| p == "*") {
+        return leaves.into_iter().map(|(_, v)| v).collect();
+    }
+
+    let mut values = vec![];
+    for pattern in &patterns {
+        if pattern.contains('*') {
+            values.extend(leaves.iter().filter(|(p, _)| wildcard_match(pattern, p)).map(|(_, v)| *v));
+        } else {
+            values.extend(field_values(doc, pattern));
+        }
+    }
+    values
+}
+
+/// Hitung berapa kata pencarian yang cocok dengan token dokumen, kata berakhiran * dicocokin awalannya
+fn text_score(query: &str, values: &[&Value], require_all: bool) -> Option<f64> {
+    let doc_tokens: Vec<String> = values.iter().flat_map(|v| tokens(&value_text(v))).collect();
+    let words: Vec<&str> = query.split_whitespace()
+        .filter(|w| !matches!(*w, "AND" | "OR" | "&&" | "||"))
+        .collect();
+
+    let mut score: f64 = 0.0;
+    let mut total = 0;
+    for word in words {
+        let prefix = word.ends_with('*');
+        let word_tokens = tokens(word);
+        if word_tokens.is_empty() {
+            continue;
+        }
+        total += 1;
+        let last = word_tokens.len() - 1;
+        let found = word_tokens.iter().enumerate().all(|(i, t)| {
+            doc_tokens.iter().any(|d| if prefix && i == last { d.starts_with(t.as_str()) } else { d == t })
+        });
+        if found {
+            score += 1.0;
+        } else if require_all {
+            return None;
+        }
+    }
+
+    if total == 0 || score > 0.0 { Some(score.max(1.0)) } else { None }
+}
+
+/// Cek dokumen cocok sama query, hasilnya skor kalo cocok
+fn score(query: &Value, id: &str, doc: &Value) -> Option<f64> {
+    let (kind, body) = match query.as_object().and_then(|q| q.iter().next()) {
+        Some(x) => x,
+        None => return Some(1.0)
+    };
+
+    match kind.as_str() {
+        "match_all" => Some(1.0),
+        "match_none" => None,
+        "ids" => {
+            let ids = body["values"].as_array()?;
+            ids.iter().any(|v| v.as_str() == Some(id)).then_some(1.0)
+        },
+        "query_string" | "simple_query_string" => {
+            let require_all = body["default_operator"].as_str().map(|o| o.eq_ignore_ascii_case("and")).unwrap_or(false);
+            text_score(body["query"].as_str().unwrap_or(""), &searchable_values(doc, body.get("fields")), require_all)
+        },
+        "multi_match" => {
+            let require_all = body["operator"].as_str().map(|o| o.eq_ignore_ascii_case("and")).unwrap_or(false);
+            let mut text = body["query"].as_str().unwrap_or("").to_string();
+            if body["type"].as_str() == Some("bool_prefix") || body["type"].as_str() == Some("phrase_prefix") {
+                text.push('*');
+            }
+            text_score(&text, &searchable_values(doc, body.get("fields")), require_all)
+        },
+        "match" | "match_phrase" | "match_bool_prefix" | "match_phrase_prefix" => {
+            let (field, spec) = single_field(body, "query")?;
+            let require_all = kind == "match_phrase" || body[field]["operator"].as_str().map(|o| o.eq_ignore_ascii_case("and")).unwrap_or(false);
+            let mut text = value_text(spec);
+            if kind.ends_with("prefix") {
+                text.push('*');
+            }
+            text_score(&text, &field_values(doc, field), require_all)
+        },
+        "term" => {
+            let (field, value) = single_field(body, "value")?;
+            field_values(doc, field).iter().any(|v| values_equal(v, value)).then_some(1.0)
+        },
+        "terms" => {
+            let (field, wanted) = body.as_object()?.iter().find(|(k, _)| k.as_str() != "boost")?;
+            let wanted = wanted.as_array()?;
+            field_values(doc, field).iter().any(|v| wanted.iter().any(|w| values_equal(v, w))).then_some(1.0)
+        },
+        "prefix" => {
+            let (field, value) = single_field(body, "value")?;
+            let prefix = value_text(value).to_lowercase();
+            field_values(doc, field).iter().any(|v| value_text(v).to_lowercase().starts_with(&prefix)).then_some(1.0)
+        },
+        "exists" => (!field_values(doc, body["field"].as_str()?).is_empty()).then_some(1.0),
+        "range" => {
+            let (field, bounds) = body.as_object()?.iter().next()?;
+            let values = field_values(doc, field);
+            values.iter().any(|v| {
+                bounds.as_object().map(|b| b.iter().all(|(op, bound)| match (op.as_str(), compare_values(v, bound)) {
+                    ("gte", Some(o)) => o != Ordering::Less,
+                    ("gt", Some(o)) => o == Ordering::Greater,
+                    ("lte", Some(o)) => o != Ordering::Greater,
+                    ("lt", Some(o)) => o == Ordering::Less,
+                    ("gte" | "gt" | "lte" | "lt", None) => false,
+                    _ => true
+                })).unwrap_or(false)
+            }).then_some(1.0)
+        },
+        "bool" => {
+            let clauses = |name: &str| -> Vec<Value> {
+                match &body[name] {
+                    Value::Array(a) => a.clone(),
+                    Value::Null => vec![],
+                    x => vec![x.clone()]
+                }
+            };
+            let mut total = 0.0;
+
+            for q in clauses("must") {
+                total += score(&q, id, doc)?;
+            }
+            for q in clauses("filter") {
+                score(&q, id, doc)?;
+            }
+            if clauses("must_not").iter().any(|q| score(q, id, doc).is_some()) {
+                return None;
+            }
+
+            let should = clauses("should");
+            let matched: Vec<f64> = should.iter().filter_map(|q| score(q, id, doc)).collect();
+            let required = match body.get("minimum_should_match").and_then(Value::as_u64) {
+                Some(n) => n as usize,
+                None if clauses("must").is_empty() && clauses("filter").is_empty() && !should.is_empty() => 1,
+                None => 0
+            };
+            if matched.len() < required {
+                return None;
+            }
+            total += matched.iter().sum::<f64>();
+            Some(total.max(1.0))
+        },
+        "constant_score" => score(&body["filter"], id, doc).map(|_| 1.0),
+        _ => None
+    }
+}
+
+/// Saring _source sesuai includes dan excludes (boleh wildcard)
+fn filter_source(source: &Value, includes: &[String], excludes: &[String]) -> Value {
+    let map = match source.as_object() {
+        Some(m) => m,
+        None => return source.clone()
+    };
+    let filtered: Map<String, Value> = map.iter()
+        .filter(|(k, _)| includes.is_empty() || includes.iter().any(|p| wildcard_match(p, k) || p.starts_with(&format!("{}.", k))))
+        .filter(|(k, _)| !excludes.iter().any(|p| wildcard_match(p, k)))
+        .map(|(k, v)| (k.clone(), v.clone()))
+        .collect();
+    Value::Object(filtered)
+}
+
+/// Baca bagian _source dari body pencarian, None berarti source tidak dikirim
+fn source_spec(spec: &Value) -> Option<(Vec<String>, Vec<String>)> {
+    let list = |v: &Value| -> Vec<String> {
+        match v {
+            Value::String(s) => vec![s.clone()],
+            Value::Array(a) => a.iter().filter_map(Value::as_str).map(str::to_string).collect(),
+            _ => vec![]
+        }
+    };
+    match spec {
+        Value::Bool(false) => None,
+        Value::Object(o) => Some((list(o.get("includes").unwrap_or(&Value::Null)), list(o.get("excludes").unwrap_or(&Value::Null)))),
+        x => Some((list(x), vec![]))
+    }
+}
+
+/// Cek nilai tanggal cocok dengan format di mapping, format yang tidak dikenal dianggap cocok
+fn date_matches_format(format: &str, value: &Value) -> bool {
+    format.split("||").any(|f| {
+        if !f.is_empty() && f.chars().all(|c| matches!(c, 'd' | 'M' | 'y' | '-' | '/' | '.')) {
+            let text = value_text(value);
+            text.len() == f.len() && f.chars().zip(text.chars()).all(|(p, c)| {
+                if matches!(p, 'd' | 'M' | 'y') { c.is_ascii_digit() } else { p == c }
+            })
+        } else {
+            true
+        }
+    })
+}
+
+/// Cek dokumen sesuai tipe di mapping, sama seperti elastic yang nolak dokumen salah tipe
+fn check_mapping(mappings: &Value, doc: &Value) -> Result<(), String> {
+    let properties = match mappings["properties"].as_object() {
+        Some(p) => p,
+        None => return Ok(())
+    };
+    for (field, mapping) in properties {
+        // Nilai yang salah tipe dilewati aja kalo mappingnya bilang begitu
+        if mapping["ignore_malformed"].as_bool().unwrap_or(false) {
+            continue;
+        }
+        for value in field_values(doc, field) {
+            let ok = match mapping["type"].as_str() {
+                Some("date") => date_matches_format(mapping["format"].as_str().unwrap_or(""), value),
+                Some("integer" | "long" | "short") => value.is_i64() || value.is_u64() || value.as_str().map(|s| s.parse::<i64>().is_ok()).unwrap_or(false),
+                _ => true
+            };
+            if !ok {
+                return Err(format!("failed to parse field [{}] of type [{}]", field, mapping["type"].as_str().unwrap_or("")));
+            }
+        }
+    }
+    Ok(())
+}
+
+#[async_trait]
+impl LibraryStore for MemoryStore {
+    async fn index_documents(&self, index: &str, data: &[(Option<String>, Value)]) -> Result<StoreResponse, Error> {
+        let mut indices = self.indices.write().unwrap();
+
+        // Sama seperti elastic, indeks yang belum ada dibuat otomatis
+        let target = self.index_or_create(&mut indices, index);
+        let mut errors = false;
+        let items: Vec<Value> = data.iter().map(|(given, doc)| {
+            let op = if given.is_some() { "create" } else { "index" };
+            let id = given.clone().unwrap_or_else(|| self.generate_id());
+            if target.docs.contains_key(&id) {
+                errors = true;
+                return json!({op: {"_index": index, "_id": id, "status": 409, "error": {"type": "version_conflict_engine_exception", "reason": format!("[{}]: version conflict, document already exists (current version [1])", id)}}});
+            }
+            match check_mapping(&target.mappings, doc) {
+                Ok(_) => {
+                    target.docs.insert(id.clone(), doc.clone());
+                    json!({op: {"_index": index, "_id": id, "result": "created", "status": 201}})
+                },
+                Err(reason) => {
+                    errors = true;
+                    json!({op: {"_index": index, "_id": id, "status": 400, "error": {"type": "mapper_parsing_exception", "reason": reason}}})
+                }
+            }
+        }).collect();
+
+        Ok(StoreResponse::new(StatusCode::OK, json!({"took": 0, "errors": errors, "items": items})))
+    }
+
+    async fn search(&self, index: &str, body: &Value, from: Option<i64>, count: Option<i64>) -> Result<StoreResponse, Error> {
+        let indices = self.indices.read().unwrap();
+        let names = match resolve(&indices, index) {
+            Ok(n) => n,
+            Err(missing) => return Ok(index_not_found(&missing))
+        };
+        let docs = names.iter().flat_map(|name| indices[name].docs.iter().map(move |(id, doc)| (name.as_str(), id.as_str(), doc))).collect();
+        Ok(StoreResponse::new(StatusCode::OK, search_docs(docs, body, from, count)))
+    }
+
+    async fn open_point_in_time(&self, index: &str, keep_alive: &str) -> Result<StoreResponse, Error> {
+        let indices = self.indices.read().unwrap();
+        let names = match resolve(&indices, index) {
+            Ok(n) => n,
+            Err(missing) => return Ok(index_not_found(&missing))
+        };
+
+        // Salin isi indeksnya sekarang, pencarian berikutnya ga lihat perubahan setelah ini
+        let snapshot: Snapshot = names.iter().map(|name| (name.clone(), indices[name].docs.clone())).collect();
+        let id = format!("pit{}", self.generate_id());
+        self.pits.write().unwrap().insert(id.clone(), (Instant::now() + keep_alive_duration(keep_alive), snapshot));
+        Ok(StoreResponse::new(StatusCode::OK, json!({"id": id})))
+    }
+
+    async fn search_point_in_time(&self, body: &Value, count: Option<i64>) -> Result<StoreResponse, Error> {
+        let id = body["pit"]["id"].as_str().unwrap_or_default();
+        let mut pits = self.pits.write().unwrap();
+        pits.retain(|_, (deadline, _)| *deadline > Instant::now());
+        let (deadline, snapshot) = match pits.get_mut(id) {
+            Some(p) => p,
+            None => return Ok(error(StatusCode::NOT_FOUND, "search_context_missing_exception", format!("No search context found for id [{}]", id)))
+        };
+        if let Some(keep_alive) = body["pit"]["keep_alive"].as_str() {
+            *deadline = Instant::now() + keep_alive_duration(keep_alive);
+        }
+
+        let docs = snapshot.iter().flat_map(|(name, docs)| docs.iter().map(move |(id, doc)| (name.as_str(), id.as_str(), doc))).collect();
+        let mut result = search_docs(docs, body, None, count);
+        result["pit_id"] = json!(id);
+        Ok(StoreResponse::new(StatusCode::OK, result))
+    }
+
+    async fn close_point_in_time(&self, id: &str) -> Result<StoreResponse, Error> {
+        let removed = self.pits.write().unwrap().remove(id).is_some();
+        Ok(StoreResponse::new(StatusCode::OK, json!({"succeeded": removed, "num_freed": removed as u8})))
+    }
+
+    async fn create_document(&self, index: &str, doc_id: &str, data: &Value) -> Result<StoreResponse, Error> {
+        let mut indices = self.indices.write().unwrap();
+        let target = self.index_or_create(&mut indices, index);
+        if target.docs.contains_key(doc_id) {
+            return Ok(error(StatusCode::CONFLICT, "version_conflict_engine_exception", format!("[{}]: version conflict, document already exists (current version [1])", doc_id)));
+        }
+        if let Err(reason) = check_mapping(&target.mappings, data) {
+            return Ok(error(StatusCode::BAD_REQUEST, "mapper_parsing_exception", reason));
+        }
+        target.docs.insert(doc_id.to_string(), data.clone());
+        Ok(StoreResponse::new(StatusCode::CREATED, json!({"_index": index, "_id": doc_id, "result": "created"})))
+    }
+
+    async fn put_document(&self, index: &str, doc_id: &str, data: &Value) -> Result<StoreResponse, Error> {
+        let mut indices = self.indices.write().unwrap();
+        let target = self.index_or_create(&mut indices, index);
+        if let Err(reason) = check_mapping(&target.mappings, data) {
+            return Ok(error(StatusCode::BAD_REQUEST, "mapper_parsing_exception", reason));
+        }
+        match target.docs.insert(doc_id.to_string(), data.clone()) {
+            Some(_) => Ok(StoreResponse::new(StatusCode::OK, json!({"_index": index, "_id": doc_id, "result": "updated"}))),
+            None => Ok(StoreResponse::new(StatusCode::CREATED, json!({"_index": index, "_id": doc_id, "result": "created"})))
+        }
+    }
+
+    async fn get_single_document(&self, index: &str, doc_id: &str, retrieve_fields: Option<String>) -> Result<StoreResponse, Error> {
+        let indices = self.indices.read().unwrap();
+        let target = match indices.get(index) {
+            Some(t) => t,
+            None => return Ok(index_not_found(index))
+        };
+        match target.docs.get(doc_id) {
+            Some(doc) => {
+                let includes: Vec<String> = retrieve_fields.unwrap_or("*".to_string()).split(',').map(|f| f.trim().to_string()).collect();
+                Ok(StoreResponse::new(StatusCode::OK, filter_source(doc, &includes, &[])))
+            },
+            None => Ok(error(StatusCode::NOT_FOUND, "resource_not_found_exception", format!("Document not found [{}]/[{}]", index, doc_id)))
+        }
+    }
+
+    async fn update_single_document(&self, index: &str, document_id: &str, data: &Value) -> Result<StoreResponse, Error> {
+        let mut indices = self.indices.write().unwrap();
+        let target = match indices.get_mut(index) {
+            Some(t) => t,
+            None => return Ok(index_not_found(index))
+        };
+
+        // Gabungin isi dokumen lama dengan yang baru, cek tipenya dulu
+        let mut merged = match target.docs.get(document_id) {
+            Some(doc) => doc.clone(),
+            None => return Ok(error(StatusCode::NOT_FOUND, "document_missing_exception", format!("[{}]: document missing", document_id)))
+        };
+        merge(&mut merged, data);
+        if let Err(reason) = check_mapping(&target.mappings, &merged) {
+            return Ok(error(StatusCode::BAD_REQUEST, "mapper_parsing_exception", reason));
+        }
+        target.docs.insert(document_id.to_string(), merged);
+
+        Ok(StoreResponse::new(StatusCode::OK, json!({"_index": index, "_id": document_id, "result": "updated"})))
+    }
+
+    async fn delete_single_document(&self, index: &str, document_id: &str) -> Result<StoreResponse, Error> {
+        let mut indices = self.indices.write().unwrap();
+        let target = match indices.get_mut(index) {
+            Some(t) => t,
+            None => return Ok(index_not_found(index))
+        };
+        match target.docs.remove(document_id) {
+            Some(_) => Ok(StoreResponse::new(StatusCode::OK, json!({"_index": index, "_id": document_id, "result": "deleted"}))),
+            None => Ok(StoreResponse::new(StatusCode::NOT_FOUND, json!({"_index": index, "_id": document_id, "result": "not_found"})))
+        }
+    }
+
+    async fn create_single_index(&self, index: &str, body: &Value) -> Result<StoreResponse, Error> {
+        let mut indices = self.indices.write().unwrap();
+        if index.is_empty() || index.chars().any(|c| c.is_uppercase() || matches!(c, '*' | ',' | ' ' | '"' | '\\' | '/' | '?' | '<' | '>' | '|')) {
+            return Ok(error(StatusCode::BAD_REQUEST, "invalid_index_name_exception", format!("Invalid index name [{}]", index)));
+        }
+        if indices.contains_key(index) {
+            return Ok(error(StatusCode::BAD_REQUEST, "resource_already_exists_exception", format!("index [{}] already exists", index)));
+        }
+
+        // Mapping dari body didahulukan, kalo ga ada pakai template
+        let mappings = match &body["mappings"] {
+            Value::Null => self.template_mappings(index),
+            m => m.clone()
+        };
+        indices.insert(index.to_string(), MemoryIndex {
+            mappings,
+            docs: BTreeMap::new()
+        });
+        Ok(StoreResponse::new(StatusCode::OK, json!({"acknowledged": true, "shards_acknowledged": true, "index": index})))
+    }
+
+    async fn get_indices(&self, index: Option<String>) -> Result<StoreResponse, Error> {
+        let indices = self.indices.read().unwrap();
+        let names = match resolve(&indices, &index.unwrap_or("*".to_string())) {
+            Ok(n) => n,
+            Err(missing) => return Ok(index_not_found(&missing))
+        };
+
+        // Bentuknya sama seperti _cat/indices dengan format json
+        let rows: Vec<Value> = names.iter().map(|name| {
+            let target = &indices[name];
+            let size = format!("{}b", target.docs.values().map(|d| d.to_string().len()).sum::<usize>());
+            json!({
+                "health": "green",
+                "status": "open",
+                "index": name,
+                "pri": "1",
+                "rep": "0",
+                "docs.count": target.docs.len().to_string(),
+                "docs.deleted": "0",
+                "store.size": size,
+                "pri.store.size": size
+            })
+        }).collect();
+        Ok(StoreResponse::new(StatusCode::OK, Value::Array(rows)))
+    }
+
+    async fn delete_single_index(&self, index: String) -> Result<StoreResponse, Error> {
+        let mut indices = self.indices.write().unwrap();
+        let names = match resolve(&indices, &index) {
+            Ok(n) => n,
+            Err(missing) => return Ok(index_not_found(&missing))
+        };
+        for name in names {
+            indices.remove(&name);
+        }
+        Ok(StoreResponse::new(StatusCode::OK, json!({"acknowledged": true})))
+    }
+
+    async fn put_index_template(&self, name: &str, body: &Value) -> Result<StoreResponse, Error> {
+        self.templates.write().unwrap().insert(name.to_string(), body.clone());
+        Ok(StoreResponse::new(StatusCode::OK, json!({"acknowledged": true})))
+    }
+
+    async fn get_mapping(&self, index: &str) -> Result<StoreResponse, Error> {
+        let indices = self.indices.read().unwrap();
+        match indices.get(index) {
+            Some(t) => Ok(StoreResponse::new(StatusCode::OK, json!({index: {"mappings": t.mappings}}))),
+            None => Ok(index_not_found(index))
+        }
+    }
+
+    async fn reindex(&self, source: &str, dest: &str) -> Result<StoreResponse, Error> {
+        let mut indices = self.indices.write().unwrap();
+        let docs = match indices.get(source) {
+            Some(t) => t.docs.clone(),
+            None => return Ok(index_not_found(source))
+        };
+
+        // Sama seperti elastic, dokumen yang ga cocok mappingnya masuk ke failures
+        let target = self.index_or_create(&mut indices, dest);
+        let mut failures = vec![];
+        let mut created = 0;
+        let mut conflicts = 0;
+        for (id, doc) in &docs {
+            // Seperti op_type create, yang sudah ada di tujuan dibiarkan
+            if target.docs.contains_key(id) {
+                conflicts += 1;
+                continue;
+            }
+            if let Err(reason) = check_mapping(&target.mappings, doc) {
+                failures.push(json!({"index": dest, "id": id, "status": 400, "cause": {"type": "mapper_parsing_exception", "reason": reason}}));
+                continue;
+            }
+            target.docs.insert(id.clone(), doc.clone());
+            created += 1;
+        }
+        Ok(StoreResponse::new(StatusCode::OK, json!({
+            "took": 0,
+            "total": docs.len(),
+            "created": created,
+            "updated": 0,
+            "version_conflicts": conflicts,
+            "failures": failures
+        })))
+    }
+}
+
+/// Cari di kumpulan dokumen (indeks, id, isi). Kalo lewat point in time, urutan terakhir tiap hasil
+/// adalah posisinya di snapshot supaya search_after bisa lanjut dari situ, seperti _shard_doc di elastic
+fn search_docs(docs: Vec<(&str, &str, &Value)>, body: &Value, from: Option<i64>, count: Option<i64>) -> Value {
+    // Kumpulin semua dokumen yang cocok beserta skornya
+    let query = body.get("query").cloned().unwrap_or(json!({"match_all": {}}));
+    let mut hits: Vec<(f64, &str, &str, &Value)> = docs.into_iter()
+        .filter_map(|(name, id, doc)| score(&query, id, doc).map(|s| (s, name, id, doc)))
+        .collect();
+    let keys = sort_keys(&body["sort"]);
+    hits.sort_by(|a, b| {
+        let by_keys = match keys.is_empty() {
+            true => b.0.partial_cmp(&a.0).unwrap_or(Ordering::Equal),
+            false => compare_by_keys(&keys, (a.0, a.3), (b.0, b.3))
+        };
+        by_keys.then(a.1.cmp(b.1)).then(a.2.cmp(b.2))
+    });
+
+    // Paginasi, lewat point in time mulai setelah posisi di search_after
+    let pit = body.get("pit").is_some();
+    let total = hits.len();
+    let from = match body["search_after"].as_array().and_then(|a| a.last()).and_then(Value::as_u64) {
+        Some(position) if pit => position as usize + 1,
+        _ => from.or(body["from"].as_i64()).unwrap_or(0).max(0) as usize
+    };
+    let size = count.or(body["size"].as_i64()).unwrap_or(20).max(0) as usize;
+    let source = source_spec(body.get("_source").unwrap_or(&Value::Null));
+    let page: Vec<Value> = hits.iter().enumerate().skip(from).take(size).map(|(position, (s, name, id, doc))| {
+        let mut hit = json!({"_index": name, "_id": id, "_score": s});
+        if let Some((includes, excludes)) = &source {
+            hit["_source"] = filter_source(doc, includes, excludes);
+        }
+        if !keys.is_empty() || pit {
+            let mut values: Vec<Value> = keys.iter().map(|(field, _)| match field.as_str() {
+                "_score" => json!(s),
+                f => field_values(doc, f).first().map(|v| (*v).clone()).unwrap_or(Value::Null)
+            }).collect();
+            if pit {
+                values.push(json!(position));
+            }
+            hit["sort"] = json!(values);
+        }
+        hit
+    }).collect();
+
+    let mut result = json!({
+        "took": 0,
+        "timed_out": false,
+        "hits": {
+            "total": {"value": total, "relation": "eq"},
+            "max_score": hits.first().map(|h| h.0),
+            "hits": page
+        }
+    });
+    if let Some(aggs) = body.get("aggs").or(body.get("aggregations")).and_then(Value::as_object) {
+        let docs: Vec<(&str, &Value)> = hits.iter().map(|h| (h.1, h.3)).collect();
+        result["aggregations"] = aggs.iter().map(|(name, spec)| (name.clone(), aggregate(spec, &docs))).collect::<Map<String, Value>>().into();
+    }
+    result
+}
+
+/// Lama point in time seperti 5m, 30s atau 1h, selain itu dianggap 5 menit
+fn keep_alive_duration(keep_alive: &str) -> Duration {
+    let (number, unit) = keep_alive.split_at(keep_alive.find(|c: char| !c.is_ascii_digit()).unwrap_or(keep_alive.len()));
+    match (number.parse::<u64>(), unit) {
+        (Ok(n), "s") => Duration::from_secs(n),
+        (Ok(n), "m") => Duration::from_secs(n * 60),
+        (Ok(n), "h") => Duration::from_secs(n * 3600),
+        _ => Duration::from_secs(300)
+    }
+}
+
+/// Hitung agregasi dari semua dokumen yang cocok beserta indeksnya, baru terms dan date_histogram per tahun yang didukung.
+/// Terms boleh punya aggs di dalamnya, dihitung dari dokumen di tiap bucket
+fn aggregate(spec: &Value, docs: &[(&str, &Value)]) -> Value {
+    if spec.get("date_histogram").is_some() {
+        return year_histogram(&spec["date_histogram"], docs);
+    }
+    let terms = &spec["terms"];
+    let field = match terms["field"].as_str() {
+        Some(f) => f,
+        None => return json!({})
+    };
+
+    // Kumpulkan dokumen untuk tiap nilai, satu dokumen masuk sekali per nilai
+    let mut groups: BTreeMap<String, (Value, AggDocs)> = BTreeMap::new();
+    for (index, doc) in docs {
+        let index_value = json!(index);
+        let values = match field {
+            "_index" => vec![&index_value],
+            _ => field_values(doc, field)
+        };
+        let mut seen = vec![];
+        for value in values.into_iter().filter(|v| !v.is_object()) {
+            let key = value_text(value);
+            if !seen.contains(&key) {
+                groups.entry(key.clone()).or_insert((value.clone(), vec![])).1.push((index, doc));
+                seen.push(key);
+            }
+        }
+    }
+    let mut buckets: Vec<(Value, AggDocs)> = groups.into_values().collect();
+    buckets.sort_by(|a, b| b.1.len().cmp(&a.1.len()).then(compare_values(&a.0, &b.0).unwrap_or(Ordering::Equal)));
+
+    let size = terms["size"].as_u64().unwrap_or(10) as usize;
+    let other: usize = buckets.iter().skip(size).map(|b| b.1.len()).sum();
+    let sub_aggs = spec.get("aggs").or(spec.get("aggregations")).and_then(Value::as_object);
+    json!({
+        "doc_count_error_upper_bound": 0,
+        "sum_other_doc_count": other,
+        "buckets": buckets.into_iter().take(size).map(|(key, bucket_docs)| {
+            let mut bucket = json!({"key": key, "doc_count": bucket_docs.len()});
+            for (name, sub) in sub_aggs.into_iter().flatten() {
+                bucket[name] = aggregate(sub, &bucket_docs);
+            }
+            bucket
+        }).collect::<Vec<Value>>()
+    })
+}
+
+/// Histogram tanggal per tahun, tahun yang kosong ga ikut seperti min_doc_count 1
+fn year_histogram(spec: &Value, docs: &[(&str, &Value)]) -> Value {
+    let field = spec["field"].as_str().unwrap_or_default();
+    let mut counts: BTreeMap<i32, usize> = BTreeMap::new();
+    for (_, doc) in docs {
+        let mut seen = vec![];
+        for value in field_values(doc, field) {
+            let year = date_key(&value_text(value)).get(..4).and_then(|y| y.parse::<i32>().ok());
+            if let Some(year) = year.filter(|y| !seen.contains(y)) {
+                *counts.entry(year).or_default() += 1;
+                seen.push(year);
+            }
+        }
+    }
+    json!({
+        "buckets": counts.into_iter().map(|(year, count)| {
+            let start = chrono::NaiveDate::from_ymd_opt(year, 1, 1).and_then(|d| d.and_hms_opt(0, 0, 0)).map(|d| d.and_utc().timestamp_millis()).unwrap_or_default();
+            json!({"key_as_string": year.to_string(), "key": start, "doc_count": count})
+        }).collect::<Vec<Value>>()
+    })
+}
+
+/// Gabungin objek secara rekursif, sama seperti partial update di elastic
+fn merge(target: &mut Value, data: &Value) {
+    match (target, data) {
+        (Value::Object(t), Value::Object(d)) => {
+            for (k, v) in d {
+                match t.get_mut(k) {
+                    Some(existing) if existing.is_object() && v.is_object() => merge(existing, v),
+                    _ => { t.insert(k.clone(), v.clone()); }
+                }
+            }
+        },
+        (t, d) => *t = d.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ids(response: &StoreResponse) -> Vec<&str> {
+        response.body["hits"]["hits"].as_array().unwrap().iter().map(|h| h["_id"].as_str().unwrap()).collect()
+    }
+
+    #[actix_web::test]
+    async fn point_in_time_pages_through_a_snapshot() {
+        let db = MemoryStore::new();
+        for (id, judul) in [("a", "Dilan"), ("b", "apel"), ("c", "Cantik"), ("d", "Bumi")] {
+            db.put_document("buku", id, &json!({"judul": judul})).await.unwrap();
+        }
+        let pit = db.open_point_in_time("buku", "1m").await.unwrap().body["id"].as_str().unwrap().to_string();
+        db.put_document("buku", "e", &json!({"judul": "Aroma"})).await.unwrap();
+
+        // Nilai urutan terakhir itu posisi di snapshot, search_after lanjut dari situ
+        let mut body = json!({"pit": {"id": pit, "keep_alive": "1m"}, "sort": [{"judul.sort": "asc"}]});
+        let first = db.search_point_in_time(&body, Some(3)).await.unwrap();
+        assert_eq!(ids(&first), ["b", "d", "c"]);
+        assert_eq!(first.body["hits"]["hits"][2]["sort"], json!(["Cantik", 2]));
+
+        body["search_after"] = json!(["Cantik", 2]);
+        let second = db.search_point_in_time(&body, Some(3)).await.unwrap();
+        assert_eq!(ids(&second), ["a"]);
+
+        db.close_point_in_time(&pit).await.unwrap();
+        assert_eq!(db.search_point_in_time(&body, Some(3)).await.unwrap().status_code(), StatusCode::NOT_FOUND);
+    }
+
+    #[actix_web::test]
+    async fn point_in_time_expires() {
+        let db = MemoryStore::new();
+        db.put_document("buku", "a", &json!({"judul": "Dilan"})).await.unwrap();
+        let pit = db.open_point_in_time("buku", "0s").await.unwrap().body["id"].as_str().unwrap().to_string();
+        let response = db.search_point_in_time(&json!({"pit": {"id": pit}}), Some(1)).await.unwrap();
+        assert_eq!(response.status_code(), StatusCode::NOT_FOUND);
+        assert_eq!(keep_alive_duration("30s"), Duration::from_secs(30));
+        assert_eq!(keep_alive_duration("2h"), Duration::from_secs(7200));
+        assert_eq!(keep_alive_duration("lama"), Duration::from_secs(300));
+    }
+}
@@ -0,0 +1,60 @@
+use serde_json::{json, Value};
+use crate::{structs::{BookSearchQuery, FieldError}, libs::*};
+
+/// Field yang bisa dipakai mengurutkan, teks diurutkan lewat sub-field sort
+pub const SORT_FIELDS: [&str; 8] = ["_score", "judul", "penulis", "penerbit", "bahasa", "isbn", "jumlah_halaman", "tanggal_terbit"];
+
+/// Paling banyak berapa kunci urutan
+const MAX_SORT_KEYS: usize = 5;
+
+/// Satu kunci urutan. Sub-field sort baru ada setelah migrate, jadi indeks lama dianggap kosong, bukan eror
+fn sort_key(field: &str, order: &str) -> Value {
+    match field {
+        "judul" | "penulis" | "penerbit" => json!({format!("{}.sort", field): {"order": order, "unmapped_type": "keyword"}}),
+        f => json!({f: order})
+    }
+}
+
+/// Urutan pencarian dari sort seperti "penulis,tanggal_terbit:desc".
+/// Tanpa arah, field naik dan _score turun. Kalo ga diisi, pencarian diurutkan dari skor dan sisanya dari judul
+pub fn search_sort(query: &BookSearchQuery) -> Result<Value, Errors> {
+    let list = match &query.sort {
+        Some(s) => s,
+        None if query.search_term.is_some() => return Ok(json!(["_score"])),
+        None => return Ok(json!([sort_key("judul", "asc")]))
+    };
+
+    let mut errors = vec![];
+    let mut keys = vec![];
+    for item in list.split(',').map(str::trim).filter(|i| !i.is_empty()) {
+        let (field, order) = match item.split_once(':') {
+            Some((f, o)) => (f.trim(), Some(o.trim().to_lowercase())),
+            None => (item, None)
+        };
+        if !SORT_FIELDS.contains(&field) {
+            errors.push(FieldError::new("sort", format!("unknown field {}, expected any of {}", field, SORT_FIELDS.join(", "))));
+            continue;
+        }
+        let order = match order.as_deref() {
+            None if field == "_score" => "desc",
+            None => "asc",
+            Some(o @ ("asc" | "desc")) => o,
+            Some(o) => {
+                errors.push(FieldError::new("sort", format!("unknown order {} for {}, expected asc or desc", o, field)));
+                continue;
+            }
+        };
+        keys.push(sort_key(field, order));
+    }
+
+    if keys.is_empty() && errors.is_empty() {
+        errors.push(FieldError::new("sort", "must name at least one field"));
+    }
+    if keys.len() > MAX_SORT_KEYS {
+        errors.push(FieldError::new("sort", format!("must have at most {} fields", MAX_SORT_KEYS)));
+    }
+    if !errors.is_empty() {
+        return Err(Errors::InvalidFields(errors));
+    }
+    Ok(json!(keys))
+}
@@ -0,0 +1,74 @@
+use actix_web::http::StatusCode;
+use async_trait::async_trait;
+use elasticsearch::Error;
+use serde_json::Value;
+
+/// Hasil satu permintaan ke penyimpanan, bentuknya sama dengan respons elastic
+pub struct StoreResponse {
+    pub status: StatusCode,
+    pub body: Value
+}
+
+impl StoreResponse {
+    pub fn new(status: StatusCode, body: Value) -> Self {
+        Self { status, body }
+    }
+
+    pub fn status_code(&self) -> StatusCode {
+        self.status
+    }
+}
+
+/// Semua operasi penyimpanan yang dipakai handler, bisa elastic atau di memori
+#[async_trait]
+pub trait LibraryStore: Send + Sync {
+    /// Buat dokumen baru secara banyak, dokumen tanpa id dibuatkan idnya.
+    /// Dokumen dengan id gagal dengan 409 kalo id sudah dipakai
+    async fn index_documents(&self, index: &str, data: &[(Option<String>, Value)]) -> Result<StoreResponse, Error>;
+
+    /// Cari dokumen di indeks
+    async fn search(&self, index: &str, body: &Value, from: Option<i64>, count: Option<i64>) -> Result<StoreResponse, Error>;
+
+    /// Buka point in time di indeks, hasilnya {"id": ...}. Pencarian lewat id itu melihat isi indeks waktu dibuka
+    async fn open_point_in_time(&self, index: &str, keep_alive: &str) -> Result<StoreResponse, Error>;
+
+    /// Cari lewat point in time, body berisi pit dan boleh berisi search_after
+    async fn search_point_in_time(&self, body: &Value, count: Option<i64>) -> Result<StoreResponse, Error>;
+
+    /// Tutup point in time yang sudah ga dipakai
+    async fn close_point_in_time(&self, id: &str) -> Result<StoreResponse, Error>;
+
+    /// Buat satu dokumen dengan id tertentu, gagal dengan 409 kalo id sudah dipakai
+    async fn create_document(&self, index: &str, doc_id: &str, data: &Value) -> Result<StoreResponse, Error>;
+
+    /// Simpan satu dokumen dengan id tertentu, kalo sudah ada isinya diganti semua
+    async fn put_document(&self, index: &str, doc_id: &str, data: &Value) -> Result<StoreResponse, Error>;
+
+    /// Ambil satu dokumen dari indeks
+    async fn get_single_document(&self, index: &str, doc_id: &str, retrieve_fields: Option<String>) -> Result<StoreResponse, Error>;
+
+    /// Update satu dokumen
+    async fn update_single_document(&self, index: &str, document_id: &str, data: &Value) -> Result<StoreResponse, Error>;
+
+    /// Hapus satu dokumen
+    async fn delete_single_document(&self, index: &str, document_id: &str) -> Result<StoreResponse, Error>;
+
+    /// Buat satu indeks baru
+    async fn create_single_index(&self, index: &str, body: &Value) -> Result<StoreResponse, Error>;
+
+    /// Ambil data statistik satu atau lebih indeks
+    async fn get_indices(&self, index: Option<String>) -> Result<StoreResponse, Error>;
+
+    /// Hapus satu indeks
+    async fn delete_single_index(&self, index: String) -> Result<StoreResponse, Error>;
+
+    /// Simpan index template, indeks baru yang namanya cocok ikut setting dan mappingnya
+    async fn put_index_template(&self, name: &str, body: &Value) -> Result<StoreResponse, Error>;
+
+    /// Ambil mapping satu indeks
+    async fn get_mapping(&self, index: &str) -> Result<StoreResponse, Error>;
+
+    /// Salin semua dokumen dari satu indeks ke indeks lain, id dokumennya tetap.
+    /// Dokumen yang id-nya sudah ada di tujuan ga ditimpa, dihitung di version_conflicts
+    async fn reindex(&self, source: &str, dest: &str) -> Result<StoreResponse, Error>;
+}
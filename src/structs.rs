@@ -1,9 +1,11 @@
 use actix_multipart::form::{MultipartForm, tempfile::TempFile};
 use serde::{Deserialize, Serialize};
+use crate::auth::Role;
 
 #[derive(Deserialize)]
 pub struct UserName{
-    pub user_name: String
+    pub user_name: String,
+    pub role: Option<Role>
 }
 
 #[derive(Deserialize)]
@@ -61,11 +63,58 @@ pub struct OptionalReturnFields{
     pub return_fields: Option<String>
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, Default)]
 pub struct Failures {
     pub doc_num: usize,
     pub reason: String,
-    pub code: i64
+    pub code: i64,
+    /// Field mana aja yang salah kalo gagal validasi
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub fields: Vec<FieldError>
+}
+
+/// Satu field buku yang isinya salah
+#[derive(Serialize, Clone, Debug)]
+pub struct FieldError {
+    pub field: String,
+    pub message: String
+}
+
+impl FieldError {
+    pub fn new(field: &str, message: impl Into<String>) -> Self {
+        Self { field: field.to_string(), message: message.into() }
+    }
+}
+
+/// Buku yang ternyata sudah ada di katalog
+#[derive(Serialize)]
+pub struct Duplicate {
+    pub doc_num: usize,
+    pub book_id: String,
+    pub genre: String,
+    pub action: DuplicatePolicy
+}
+
+/// Hasil bikin buku sekaligus
+#[derive(Serialize)]
+pub struct BulkResult {
+    pub failures: Vec<Failures>,
+    pub duplicates: Vec<Duplicate>
+}
+
+/// Apa yang dilakukan kalo buku yang dikirim sudah ada
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum DuplicatePolicy {
+    #[default]
+    Reject,
+    Skip,
+    Upsert
+}
+
+#[derive(Deserialize)]
+pub struct DuplicateQuery {
+    pub on_duplicate: Option<DuplicatePolicy>
 }
 
 #[derive(Deserialize)]
@@ -75,30 +124,174 @@ pub struct UserBookID {
     pub book_id: String
 }
 
+#[derive(Deserialize)]
+pub struct UserIsbn {
+    pub user_id: String,
+    pub isbn: String
+}
+
 #[derive(Deserialize)]
 pub struct BookSearchQuery {
     pub genre: Option<String>,
     pub search_term: Option<String>,
+    /// Field yang dicari dan yang dikirim balik, dipisah koma
+    pub search_fields: Option<String>,
+    pub return_fields: Option<String>,
+    /// Tambahin jumlah eksemplar yang tersedia di tiap buku
+    pub copies: Option<bool>,
+    /// Facet yang dihitung dipisah koma, dan paling banyak berapa nilai per facet
+    pub facets: Option<String>,
+    pub facet_size: Option<i64>,
+    /// Nilai facet yang dipilih, dipakai sebagai filter
+    pub penulis: Option<String>,
+    pub penerbit: Option<String>,
+    pub bahasa: Option<String>,
+    pub book_genre: Option<String>,
+    pub tahun: Option<i32>,
+    /// Filter lain: judul dan isbn yang sama, range halaman dan tanggal terbit (dd-MM-yyyy),
+    /// lalu field yang harus ada atau harus kosong dipisah koma
+    pub judul: Option<String>,
+    pub isbn: Option<String>,
+    pub jumlah_halaman_min: Option<i64>,
+    pub jumlah_halaman_max: Option<i64>,
+    pub tanggal_terbit_from: Option<String>,
+    pub tanggal_terbit_to: Option<String>,
+    pub exists: Option<String>,
+    pub missing: Option<String>,
+    /// Urutan dipisah koma, misalnya penulis,tanggal_terbit:desc
+    pub sort: Option<String>,
+    /// Paginasi pakai cursor, use_cursor untuk halaman pertama lalu kirim balik next_cursor
+    pub use_cursor: Option<bool>,
+    pub cursor: Option<String>,
     pub from: Option<i64>,
     pub count: Option<i64>
 }
 
-#[derive(Serialize, Deserialize)]
-pub struct BookInput {
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub isbn: Option<String>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub judul: Option<String>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub penulis: Option<String>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub penerbit: Option<String>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub genre: Option<Vec<String>>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub bahasa: Option<String>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub jumlah_halaman: Option<usize>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub tanggal_terbit: Option<String>
-}
\ No newline at end of file
+#[derive(Deserialize)]
+pub struct UpdateQuery {
+    /// Buat bukunya kalo belum ada, kalo sudah ada isinya diganti semua
+    pub upsert: Option<bool>
+}
+
+#[derive(Deserialize)]
+pub struct SuggestQuery {
+    /// Teks yang sedang diketik
+    pub q: String,
+    pub genre: Option<String>,
+    pub count: Option<i64>
+}
+
+#[derive(Deserialize)]
+pub struct LoanInput {
+    pub borrower: String,
+    pub days: Option<i64>,
+    /// Wajib kalo bukunya punya eksemplar
+    pub barcode: Option<String>
+}
+
+#[derive(Deserialize)]
+pub struct OptionalBarcode {
+    pub barcode: Option<String>
+}
+
+/// Status peminjaman yang mau dilihat
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum LoanStatus {
+    #[default]
+    Active,
+    Overdue,
+    Returned
+}
+
+#[derive(Deserialize)]
+pub struct LoanQuery {
+    pub status: Option<LoanStatus>,
+    pub borrower: Option<String>,
+    pub from: Option<i64>,
+    pub count: Option<i64>
+}
+
+/// Status satu eksemplar fisik
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum CopyStatus {
+    Available,
+    OnLoan,
+    /// Disiapkan untuk pemesan yang antre
+    OnHold,
+    Lost,
+    Repair,
+    Retired
+}
+
+#[derive(Deserialize)]
+pub struct CopyInput {
+    pub barcode: String,
+    pub condition: Option<String>,
+    pub location: Option<String>
+}
+
+#[derive(Deserialize)]
+pub struct CopyUpdate {
+    pub condition: Option<String>,
+    pub location: Option<String>,
+    pub status: Option<CopyStatus>
+}
+
+#[derive(Deserialize)]
+pub struct UserBarcode {
+    pub user_id: String,
+    pub barcode: String
+}
+
+/// Status pesanan, waiting dan ready masih di antrean
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum HoldStatus {
+    Waiting,
+    Ready,
+    Fulfilled,
+    Cancelled,
+    Expired
+}
+
+#[derive(Deserialize)]
+pub struct HoldInput {
+    /// Kosong berarti yang manggil API sendiri
+    pub patron: Option<String>
+}
+
+#[derive(Deserialize)]
+pub struct HoldQuery {
+    pub status: Option<HoldStatus>,
+    pub from: Option<i64>,
+    pub count: Option<i64>
+}
+
+/// Jenis catatan di buku denda
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum LedgerKind {
+    Charge,
+    Payment,
+    Waiver
+}
+
+#[derive(Deserialize)]
+pub struct UserBorrower {
+    pub user_id: String,
+    pub borrower: String
+}
+
+#[derive(Deserialize)]
+pub struct LedgerInput {
+    pub amount: i64,
+    pub note: Option<String>
+}
+
+#[derive(Deserialize)]
+pub struct LedgerQuery {
+    pub from: Option<i64>,
+    pub count: Option<i64>
+}
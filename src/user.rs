@@ -1,88 +1,119 @@
-use actix_web::{HttpResponse, web::{self, Data}, http::StatusCode};
-use serde_json::{json, Value};
-use crate::{USER_LIST, database::Database, libs::*};
+use actix_web::{HttpResponse, web::{self, Data}};
+use serde_json::json;
+use crate::{auth::*, config::Config, mapping::book_index, store::LibraryStore, libs::*};
 use super::structs::*;
 
 // Ambil list usernya
-pub async fn get_user_list(path: web::Path<GetUserList>, db: Data::<Database>) -> HttpResponse{
+pub async fn get_user_list(query: web::Query<GetUserList>, cfg: Data<Config>, db: Data::<dyn LibraryStore>) -> Result<HttpResponse, Errors>{
     // Cek kalo elastic hidup
-    if !check_server(&db).await { return HttpResponse::build(StatusCode::SERVICE_UNAVAILABLE).json(json!({"error": Errors::ServerDown.to_string()}))}
+    check_server(db.get_ref()).await?;
 
     // Harus selalu ada list usernya
-    create_new_genre(None, USER_LIST, &db).await;
+    create_new_genre(None, &cfg.library.user_index, &cfg, db.get_ref()).await?;
 
-    // Ambil semua usernya
+    // Ambil semua usernya, kalo ada nama yang dikasih cari berdasarkan nama
+    let user_query = match &query.user_name {
+        Some(name) => json!({"match": {"name": name}}),
+        None => json!({"match_all": {}})
+    };
     let body =
         json!({
             "_source": {
-                "includes": "*"
-            },
-            "query": {
-                "match_all": {} 
+                "includes": "*",
+                "excludes": ["api_key_hash"]
             },
+            "query": user_query,
         });
-    HttpResponse::Ok().json(db.search(USER_LIST, &body, path.from, path.count).await.unwrap().json::<Value>().await.unwrap()["hits"]["hits"].clone())
+    let response = db.search(&cfg.library.user_index, &body, query.from, Some(query.count.unwrap_or(cfg.library.default_page_size))).await?;
+    if !response.status_code().is_success() {
+        return Err(store_error(&response, Errors::Unknown));
+    }
+    Ok(HttpResponse::Ok().json(&response.body["hits"]["hits"]))
 }
 
 /// Ambil data satu user
-pub async fn get_a_user(path: web::Path<UserID>, db: Data::<Database>) -> HttpResponse{
+pub async fn get_a_user(path: web::Path<UserID>, cfg: Data<Config>, db: Data::<dyn LibraryStore>) -> Result<HttpResponse, Errors>{
     // Cek kalo elastic hidup
-    if !check_server(&db).await { return HttpResponse::build(StatusCode::SERVICE_UNAVAILABLE).json(json!({"error": Errors::ServerDown.to_string()}))}
+    check_server(db.get_ref()).await?;
 
     // Harus selalu ada list usernya
-    create_new_genre(None, USER_LIST, &db).await;
+    create_new_genre(None, &cfg.library.user_index, &cfg, db.get_ref()).await?;
 
     // Ambil data dari satu user
-    match get_book(USER_LIST, &path.user_id, Some("_id,name,genres".to_string()), &db).await {
-        Ok((s, v)) => HttpResponse::build(s).json(v),
-        Err((s, e)) => match e {
-            Errors::BookNotFound(_) => HttpResponse::build(s).json(json!({"error": Errors::UserNotFound(path.user_id.to_string()).to_string()})),
-            _ => HttpResponse::build(s).json(json!({"error": e.to_string()}))
-        }
+    match get_book(&cfg.library.user_index, &path.user_id, Some("_id,name,role,genres".to_string()), db.get_ref()).await {
+        Ok(v) => Ok(HttpResponse::Ok().json(v)),
+        Err(Errors::BookNotFound(_)) => Err(Errors::UserNotFound(path.user_id.to_string())),
+        Err(e) => Err(e)
     }
 }
 
 /// Buat user baru
-pub async fn create_new_user(data: web::Json<UserName>, db: Data::<Database>) -> HttpResponse{
+pub async fn create_new_user(data: web::Json<UserName>, cfg: Data<Config>, db: Data::<dyn LibraryStore>) -> Result<HttpResponse, Errors>{
     // Cek kalo elastic hidup
-    if !check_server(&db).await { return HttpResponse::ServiceUnavailable().json(json!({"error": Errors::ServerDown.to_string()})) }
+    check_server(db.get_ref()).await?;
 
     // Harus selalu ada list usernya
-    create_new_genre(None, USER_LIST, &db).await;
+    create_new_genre(None, &cfg.library.user_index, &cfg, db.get_ref()).await?;
+
+    // Bikin user baru, yang disimpan cuma hash dari API keynya
+    let (secret, hash) = generate_secret();
+    let user = json!({"name": data.user_name, "role": data.role.unwrap_or_default(), "api_key_hash": hash});
+    let response = db.index_documents(&cfg.library.user_index, &[(None, user)]).await?;
+    if let Some(fail) = bulk_failures(&response)?.first() {
+        return Err(Errors::Validation(fail.reason.clone()));
+    }
 
-    // Bikin user baru
-    HttpResponse::build(db.index_documents(USER_LIST, &vec![json!({"name": data.user_name})]).await.unwrap().status_code()).finish()
+    // API key cuma dikirim sekali ini
+    let user_id = response.body["items"][0]["index"]["_id"].as_str().ok_or(Errors::Unknown)?;
+    Ok(HttpResponse::Created().json(json!({"user_id": user_id, "api_key": format_api_key(user_id, &secret)})))
 }
 
 // Update data satu user
-pub async fn update_user(data: web::Json<UpdateUser>, db: Data::<Database>) -> HttpResponse{
+pub async fn update_user(data: web::Json<UpdateUser>, caller: Caller, cfg: Data<Config>, db: Data::<dyn LibraryStore>) -> Result<HttpResponse, Errors>{
+    // User id ada di body, jadi izinnya dicek di sini bukan di route
+    caller.require(Permission::ManageAccount, Some(&data.user_id))?;
+
     // Cek kalo elastic hidup
-    if !check_server(&db).await { return HttpResponse::build(StatusCode::SERVICE_UNAVAILABLE).json(json!({"error": Errors::ServerDown.to_string()}))}
+    check_server(db.get_ref()).await?;
 
     // Update data user
-    HttpResponse::build(db.update_single_document(USER_LIST, &data.user_id,json!({"name": &data.user_name})).await.unwrap().status_code()).finish()
+    let response = db.update_single_document(&cfg.library.user_index, &data.user_id, &json!({"name": &data.user_name})).await?;
+    if !response.status_code().is_success() {
+        return Err(store_error(&response, Errors::UserNotFound(data.user_id.to_string())));
+    }
+    Ok(HttpResponse::build(response.status_code()).finish())
 }
 
 // Hapus satu user
-pub async fn delete_user(path: web::Path<UserID>, db: Data::<Database>) -> HttpResponse{
+pub async fn delete_user(path: web::Path<UserID>, cfg: Data<Config>, db: Data::<dyn LibraryStore>) -> Result<HttpResponse, Errors>{
     // Cek kalo elastic hidup
-    if !check_server(&db).await { return HttpResponse::build(StatusCode::SERVICE_UNAVAILABLE).json(json!({"error": Errors::ServerDown.to_string()}))}
+    check_server(db.get_ref()).await?;
 
-    // Ambil list genre dari usernya
-    match get_user_genre_list(&path.user_id, &db).await {
+    // Ambil list genre dari usernya, kalo ga ketemu langsung gagal
+    let l = get_user_genre_list(&path.user_id, &cfg, db.get_ref()).await?;
 
-        // Kalo user ada, ambil genrenya
-        Ok(l) => {
-            // Loop untuk hapus semua genrenya 
-            for i in l {
-                let _ = db.delete_single_index(format!("{}.{}", &path.user_id.to_lowercase(), &i)).await;
-            }
+    // Loop untuk hapus semua genrenya
+    for i in l {
+        let _ = db.delete_single_index(book_index(&path.user_id, &i)).await;
+    }
 
-            // Lalu hapus usernya
-            HttpResponse::build(db.delete_single_document(USER_LIST, &path.user_id).await.unwrap().status_code()).finish()
-        },
+    // Lalu hapus usernya
+    let response = db.delete_single_document(&cfg.library.user_index, &path.user_id).await?;
+    if !response.status_code().is_success() {
+        return Err(store_error(&response, Errors::UserNotFound(path.user_id.to_string())));
+    }
+    Ok(HttpResponse::build(response.status_code()).finish())
+}
 
-        // Kalo ga ketemu
-        Err((s, e)) => HttpResponse::build(s).json(json!({"error": e.to_string()})),
+/// Ganti API key user, key yang lama langsung tidak berlaku
+pub async fn rotate_api_key(path: web::Path<UserID>, cfg: Data<Config>, db: Data::<dyn LibraryStore>) -> Result<HttpResponse, Errors>{
+    // Cek kalo elastic hidup
+    check_server(db.get_ref()).await?;
+
+    let (secret, hash) = generate_secret();
+    let response = db.update_single_document(&cfg.library.user_index, &path.user_id, &json!({"api_key_hash": hash})).await?;
+    if !response.status_code().is_success() {
+        return Err(store_error(&response, Errors::UserNotFound(path.user_id.to_string())));
     }
-}
\ No newline at end of file
+    Ok(HttpResponse::Ok().json(json!({"user_id": path.user_id, "api_key": format_api_key(&path.user_id, &secret)})))
+}
@@ -0,0 +1,229 @@
+use chrono::{Datelike, Duration, NaiveDate, Utc};
+use serde_json::{json, Value};
+use crate::{isbn::{normalize, normalize_book}, libs::Errors, structs::FieldError};
+
+/// Field yang boleh ada di dokumen buku
+const FIELDS: [&str; 8] = ["isbn", "judul", "penulis", "penerbit", "genre", "bahasa", "jumlah_halaman", "tanggal_terbit"];
+
+/// Field yang bisa dicari pakai search_fields
+pub const SEARCH_FIELDS: [&str; 6] = ["isbn", "judul", "penulis", "penerbit", "genre", "bahasa"];
+
+/// Field yang bisa diambil pakai return_fields, termasuk yang dibuat waktu isbn dirapikan
+pub const RETURN_FIELDS: [&str; 10] = ["isbn", "isbn_10", "isbn_raw", "judul", "penulis", "penerbit", "genre", "bahasa", "jumlah_halaman", "tanggal_terbit"];
+
+/// Kode bahasa ISO 639-1
+const LANGUAGES: [&str; 184] = [
+    "aa", "ab", "ae", "af", "ak", "am", "an", "ar", "as", "av", "ay", "az", "ba", "be", "bg", "bh", "bi", "bm", "bn", "bo",
+    "br", "bs", "ca", "ce", "ch", "co", "cr", "cs", "cu", "cv", "cy", "da", "de", "dv", "dz", "ee", "el", "en", "eo", "es",
+    "et", "eu", "fa", "ff", "fi", "fj", "fo", "fr", "fy", "ga", "gd", "gl", "gn", "gu", "gv", "ha", "he", "hi", "ho", "hr",
+    "ht", "hu", "hy", "hz", "ia", "id", "ie", "ig", "ii", "ik", "io", "is", "it", "iu", "ja", "jv", "ka", "kg", "ki", "kj",
+    "kk", "kl", "km", "kn", "ko", "kr", "ks", "ku", "kv", "kw", "ky", "la", "lb", "lg", "li", "ln", "lo", "lt", "lu", "lv",
+    "mg", "mh", "mi", "mk", "ml", "mn", "mr", "ms", "mt", "my", "na", "nb", "nd", "ne", "ng", "nl", "nn", "no", "nr", "nv",
+    "ny", "oc", "oj", "om", "or", "os", "pa", "pi", "pl", "ps", "pt", "qu", "rm", "rn", "ro", "ru", "rw", "sa", "sc", "sd",
+    "se", "sg", "si", "sk", "sl", "sm", "sn", "so", "sq", "sr", "ss", "st", "su", "sv", "sw", "ta", "te", "tg", "th", "ti",
+    "tk", "tl", "tn", "to", "tr", "ts", "tt", "tw", "ty", "ug", "uk", "ur", "uz", "ve", "vi", "vo", "wa", "wo", "xh", "yi",
+    "yo", "za", "zh", "zu"
+];
+
+/// Buku tertua yang dicetak, Gutenberg sekitar 1450
+const FIRST_YEAR: i32 = 1450;
+const MAX_PAGES: u64 = 100_000;
+const MAX_GENRES: usize = 20;
+
+/// Cek teks wajib berupa string dan ga kepanjangan, hasilnya teks yang sudah dirapikan
+fn text<'a>(field: &str, value: &'a Value, max: usize, errors: &mut Vec<FieldError>) -> Option<&'a str> {
+    match value.as_str() {
+        Some(s) if s.chars().count() > max => {
+            errors.push(FieldError::new(field, format!("must be at most {} characters", max)));
+            None
+        },
+        Some(s) => Some(s.trim()),
+        None => {
+            errors.push(FieldError::new(field, "must be a string"));
+            None
+        }
+    }
+}
+
+/// Tanggal terbit harus dd-MM-yyyy, ga sebelum buku cetak pertama dan paling lama setahun ke depan
+fn check_date(value: &str) -> Result<(), String> {
+    let date = NaiveDate::parse_from_str(value, "%d-%m-%Y")
+        .ok()
+        .filter(|_| value.len() == 10)
+        .ok_or("must be a valid date in dd-MM-yyyy format")?;
+    let latest = Utc::now().date_naive() + Duration::days(365);
+    if date.year() < FIRST_YEAR || date > latest {
+        return Err(format!("must be between 01-01-{} and {}", FIRST_YEAR, latest.format("%d-%m-%Y")));
+    }
+    Ok(())
+}
+
+/// Cek semua field buku sebelum dikirim ke elastic, lalu rapikan isbn dan bahasanya.
+/// Kalo sebagian, field yang ga dikirim ga dicek, selain itu judul wajib ada
+pub fn check_book(book: &mut Value, partial: bool) -> Result<(), Vec<FieldError>> {
+    let fields = match book.as_object() {
+        Some(f) => f,
+        None => return Err(vec![FieldError::new("", "book must be a JSON object")])
+    };
+    let mut errors = vec![];
+
+    for (field, value) in fields {
+        if !FIELDS.contains(&field.as_str()) {
+            errors.push(FieldError::new(field, "unknown field"));
+            continue;
+        }
+        if value.is_null() {
+            continue;
+        }
+        match field.as_str() {
+            "judul" => {
+                if let Some("") = text(field, value, 500, &mut errors) {
+                    errors.push(FieldError::new(field, "must not be empty"));
+                }
+            },
+            "penulis" | "penerbit" => {
+                text(field, value, 300, &mut errors);
+            },
+            "isbn" => {
+                if let Some(isbn) = text(field, value, 32, &mut errors).filter(|i| !i.is_empty()) {
+                    if let Err(e) = normalize(isbn) {
+                        errors.push(FieldError::new(field, e));
+                    }
+                }
+            },
+            "bahasa" => {
+                if let Some(code) = text(field, value, 2, &mut errors) {
+                    if !LANGUAGES.contains(&code.to_lowercase().as_str()) {
+                        errors.push(FieldError::new(field, "must be an ISO 639-1 language code, e.g. id or en"));
+                    }
+                }
+            },
+            "genre" => match value.as_array() {
+                Some(genres) if genres.len() > MAX_GENRES => errors.push(FieldError::new(field, format!("must have at most {} items", MAX_GENRES))),
+                Some(genres) => for (i, g) in genres.iter().enumerate() {
+                    text(&format!("genre[{}]", i), g, 100, &mut errors);
+                },
+                None => errors.push(FieldError::new(field, "must be a list of strings"))
+            },
+            "jumlah_halaman" => match value.as_u64() {
+                Some(n) if (1..=MAX_PAGES).contains(&n) => (),
+                _ => errors.push(FieldError::new(field, format!("must be a whole number between 1 and {}", MAX_PAGES)))
+            },
+            "tanggal_terbit" => {
+                if let Some(date) = text(field, value, 10, &mut errors) {
+                    if let Err(e) = check_date(date) {
+                        errors.push(FieldError::new(field, e));
+                    }
+                }
+            },
+            _ => ()
+        }
+    }
+
+    if !partial && fields.get("judul").map(Value::is_null).unwrap_or(true) {
+        errors.push(FieldError::new("judul", "is required"));
+    }
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+
+    // Semua sudah benar, tinggal dirapikan
+    if let Some(code) = book["bahasa"].as_str().map(str::to_lowercase) {
+        book["bahasa"] = json!(code);
+    }
+    normalize_book(book).map_err(|e| vec![FieldError::new("isbn", e)])
+}
+
+/// Pisahkan daftar field yang dipisah koma lalu cek semuanya dikenal
+pub fn parse_fields(param: &str, list: &str, allowed: &[&str]) -> Result<Vec<String>, Errors> {
+    let fields: Vec<String> = list.split(',').map(str::trim).filter(|f| !f.is_empty()).map(str::to_string).collect();
+    let unknown: Vec<&str> = fields.iter().map(String::as_str).filter(|f| !allowed.contains(f)).collect();
+    if fields.is_empty() {
+        return Err(Errors::InvalidFields(vec![FieldError::new(param, "must name at least one field")]));
+    }
+    if !unknown.is_empty() {
+        return Err(Errors::InvalidFields(vec![FieldError::new(param, format!("unknown fields {}, expected any of {}", unknown.join(", "), allowed.join(", ")))]));
+    }
+    Ok(fields)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fields(errors: Vec<FieldError>) -> Vec<String> {
+        errors.into_iter().map(|e| e.field).collect()
+    }
+
+    #[test]
+    fn valid_book_is_tidied() {
+        let mut book = json!({
+            "judul": "Laskar Pelangi",
+            "isbn": "979-3062-79-7",
+            "bahasa": "ID",
+            "genre": ["fiksi"],
+            "jumlah_halaman": 529,
+            "tanggal_terbit": "01-09-2005"
+        });
+        check_book(&mut book, false).unwrap();
+        assert_eq!(book["bahasa"], "id");
+        assert_eq!(book["isbn"], "9789793062792");
+        assert_eq!(book["isbn_raw"], "979-3062-79-7");
+    }
+
+    #[test]
+    fn every_wrong_field_is_listed() {
+        let mut book = json!({
+            "judul": "",
+            "penulis": 7,
+            "isbn": "123",
+            "bahasa": "xx",
+            "genre": "fiksi",
+            "jumlah_halaman": 0,
+            "tanggal_terbit": "2005-09-01",
+            "warna": "biru"
+        });
+        let mut wrong = fields(check_book(&mut book, false).unwrap_err());
+        wrong.sort();
+        assert_eq!(wrong, ["bahasa", "genre", "isbn", "judul", "jumlah_halaman", "penulis", "tanggal_terbit", "warna"]);
+    }
+
+    #[test]
+    fn judul_only_required_when_creating() {
+        assert_eq!(fields(check_book(&mut json!({"penulis": "Andrea"}), false).unwrap_err()), ["judul"]);
+        assert_eq!(fields(check_book(&mut json!({"judul": null}), false).unwrap_err()), ["judul"]);
+        check_book(&mut json!({"penulis": "Andrea"}), true).unwrap();
+        assert_eq!(fields(check_book(&mut json!([]), true).unwrap_err()), [""]);
+    }
+
+    #[test]
+    fn limits() {
+        let genres: Vec<String> = (0..=MAX_GENRES).map(|i| i.to_string()).collect();
+        assert_eq!(fields(check_book(&mut json!({"genre": genres}), true).unwrap_err()), ["genre"]);
+        assert_eq!(fields(check_book(&mut json!({"genre": ["a", 1]}), true).unwrap_err()), ["genre[1]"]);
+        assert_eq!(fields(check_book(&mut json!({"judul": "a".repeat(501)}), true).unwrap_err()), ["judul"]);
+        check_book(&mut json!({"jumlah_halaman": MAX_PAGES}), true).unwrap();
+        assert!(check_book(&mut json!({"jumlah_halaman": MAX_PAGES + 1}), true).is_err());
+        assert!(check_book(&mut json!({"jumlah_halaman": 1.5}), true).is_err());
+    }
+
+    #[test]
+    fn publish_dates() {
+        assert!(check_date("01-01-1450").is_ok());
+        assert!(check_date("31-12-1449").is_err());
+        assert!(check_date("30-02-2005").is_err());
+        assert!(check_date("1-9-2005").is_err());
+        let too_late = (Utc::now().date_naive() + Duration::days(366)).format("%d-%m-%Y").to_string();
+        assert!(check_date(&too_late).is_err());
+    }
+
+    #[test]
+    fn field_lists() {
+        assert_eq!(parse_fields("return_fields", " judul, isbn_10 ,", &RETURN_FIELDS).unwrap(), ["judul", "isbn_10"]);
+        assert!(matches!(parse_fields("return_fields", " , ", &RETURN_FIELDS), Err(Errors::InvalidFields(_))));
+        match parse_fields("search_fields", "judul,isbn_10", &SEARCH_FIELDS) {
+            Err(Errors::InvalidFields(e)) => assert!(e[0].message.starts_with("unknown fields isbn_10")),
+            _ => panic!("isbn_10 is not searchable")
+        }
+    }
+}